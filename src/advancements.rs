@@ -0,0 +1,96 @@
+use crate::{
+    AppState, Error, Page,
+    filters::{self, Locale},
+    logs, negotiate,
+};
+use askama::Template;
+use axum::{extract::State, http::HeaderMap, response::IntoResponse};
+use chrono::NaiveDateTime;
+use futures::StreamExt;
+use serde::Serialize;
+use std::future::ready;
+use utoipa::ToSchema;
+
+const ADVANCEMENT_MESSAGES: &[&str] = &[
+    "has made the advancement",
+    "has reached the goal",
+    "has completed the challenge",
+];
+
+#[derive(Debug, Serialize, ToSchema)]
+struct Advancement {
+    name: String,
+    timestamp: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = AdvancementsPlayer)]
+struct Player {
+    name: String,
+    total_advancements: usize,
+    advancements: Vec<Advancement>,
+}
+
+#[derive(Debug, Template, Serialize, ToSchema)]
+#[template(path = "advancements/index.html")]
+struct AdvancementsTemplate {
+    #[serde(skip)]
+    #[schema(ignore)]
+    locale: Locale,
+    players: Vec<Player>,
+}
+
+impl Page for AdvancementsTemplate {}
+
+/// Extracts the advancement name from messages like `has made the advancement [Stone Age]`.
+fn parse_advancement(message: &str) -> Option<&str> {
+    ADVANCEMENT_MESSAGES.iter().find_map(|prefix| {
+        message
+            .strip_prefix(prefix)?
+            .trim()
+            .strip_prefix('[')?
+            .strip_suffix(']')
+    })
+}
+
+#[utoipa::path(get, path = "/advancements", responses((status = 200, body = AdvancementsTemplate)))]
+pub async fn advancements(
+    State(AppState { config, .. }): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, Error> {
+    let mut players = Vec::<Player>::new();
+    logs::parse_logs(&config)
+        .await?
+        .for_each(|line| {
+            if let Some(name) = parse_advancement(&line.message) {
+                let player = match players.iter_mut().find(|p| p.name == line.player) {
+                    Some(p) => p,
+                    None => {
+                        players.push(Player {
+                            name: line.player.clone(),
+                            total_advancements: 0,
+                            advancements: vec![],
+                        });
+                        players.last_mut().unwrap()
+                    }
+                };
+                player.total_advancements += 1;
+                player.advancements.push(Advancement {
+                    name: name.to_owned(),
+                    timestamp: line.timestamp,
+                });
+            }
+            ready(())
+        })
+        .await;
+    players.sort_by_key(|p| std::cmp::Reverse(p.total_advancements));
+
+    negotiate(
+        &config,
+        &headers,
+        AdvancementsTemplate {
+            locale: config.locale,
+            players,
+        },
+    )
+}
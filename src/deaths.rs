@@ -1,13 +1,39 @@
-use crate::{Config, Error, logs};
+use crate::{
+    AppState, Config, Error, Page, SharedState, etag_matches,
+    filters::{self, Locale},
+    logs::{self, LogLine},
+    negotiate, playtime, wants_json,
+};
 use askama::Template;
 use axum::{
-    extract::{Query, State},
-    response::{Html, IntoResponse},
+    Json,
+    body::Body,
+    extract::{FromRef, Path, Query, State},
+    http::{HeaderMap, StatusCode, header::CONTENT_TYPE},
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use chrono::format::StrftimeItems;
+use chrono::{
+    DateTime, Datelike, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta,
+    TimeZone, Timelike,
 };
-use chrono::{Datelike, Days, NaiveDate, NaiveDateTime, NaiveTime};
-use futures::StreamExt;
+use chrono_tz::Tz;
+use futures::{Stream, StreamExt};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, future::ready, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::ready,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::RwLock;
+use utoipa::{IntoParams, ToSchema};
 
 macro_rules! ts {
     ($d:literal Jan $y:literal $h:literal : $mm:literal : $s:literal : $ms:literal) => {
@@ -40,7 +66,6 @@ const IGNORED_MESSAGES: &[&str] = &[
     "has reached the goal",
     "has completed the challenge",
     "[Server]",
-    "<",
     "moved too quickly!",
     "moved wrongly!",
     "logged in with entity id",
@@ -50,16 +75,114 @@ const IGNORED_MESSAGES: &[&str] = &[
     "teleported to",
 ];
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+/// Converts a timestamp in the host's local time, as written by the server, to the given timezone.
+fn localize(timestamp: NaiveDateTime, tz: Tz) -> NaiveDateTime {
+    match Local.from_local_datetime(&timestamp).earliest() {
+        Some(local) => local.with_timezone(&tz).naive_local(),
+        // the timestamp falls in a DST gap, there is nothing sensible to convert
+        None => timestamp,
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+struct DeathRecord {
+    player: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uuid: Option<String>,
+    timestamp: NaiveDateTime,
+    cause: String,
+}
+
+/// A broad kind of death, for a higher level view than the causes themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Category {
+    Mob,
+    Fall,
+    Fire,
+    Lava,
+    Drowning,
+    Explosion,
+    PvP,
+    Starvation,
+    Other,
+}
+
+/// Phrases after which vanilla death messages name whoever is to blame, the ones that blame
+/// someone other than what dealt the blow first, as in `was killed by magic whilst trying to
+/// escape bob`.
+const KILLER_PHRASES: &[&str] = &[
+    " to escape ",
+    " whilst fighting ",
+    " trying to hurt ",
+    " by ",
+];
+
+/// Whoever a death is blamed on, a mob or a player, e.g. `Zombie` in `was slain by Zombie using
+/// [Iron Sword]`. Blocks, as in `was squashed by a falling anvil`, aren't anyone.
+fn killer(cause: &str) -> Option<&str> {
+    let killer = KILLER_PHRASES
+        .iter()
+        .find_map(|p| cause.find(p).map(|i| &cause[i + p.len()..]))?;
+    let killer = killer.split(" using ").next().unwrap_or(killer).trim();
+    (!killer.is_empty() && !killer.starts_with("a ")).then_some(killer)
+}
+
+impl Category {
+    /// Categorizes a cause by the phrasing of vanilla death messages. `players` are the names
+    /// that make a death PvP when they are the [`killer`]. Only the phrasing before the killer
+    /// is looked at, so a killer whose name matches a phrase, like a Drowned, doesn't decide the
+    /// category.
+    fn of(cause: &str, players: &[&str]) -> Self {
+        const PHRASES: &[(&str, Category)] = &[
+            ("lava", Category::Lava),
+            ("drowned", Category::Drowning),
+            ("starved", Category::Starvation),
+            ("blew up", Category::Explosion),
+            ("blown up", Category::Explosion),
+            ("went up in flames", Category::Fire),
+            ("burned to death", Category::Fire),
+            ("burnt to a crisp", Category::Fire),
+            ("walked into fire", Category::Fire),
+            ("fell", Category::Fall),
+            ("hit the ground too hard", Category::Fall),
+            ("doomed to fall", Category::Fall),
+            ("stung to death", Category::Mob),
+        ];
+        let killer = killer(cause);
+        match killer {
+            // the bed explosions of the nether and the end are blamed on the game
+            Some("[Intentional Game Design]") => return Self::Explosion,
+            Some(killer) if players.contains(&killer) => return Self::PvP,
+            _ => {}
+        }
+        let phrasing = killer
+            .and_then(|k| cause.rfind(k))
+            .map_or(cause, |i| &cause[..i]);
+        match PHRASES.iter().find(|(p, _)| phrasing.contains(p)) {
+            Some((_, category)) => *category,
+            None if killer.is_some() => Self::Mob,
+            None => Self::Other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, ToSchema)]
 struct Chart {
     labels: Vec<String>,
     values: Vec<u64>,
+    /// Extra text for each data point, shown in its tooltip. Empty when the chart has none.
+    #[serde(default)]
+    annotations: Vec<String>,
 }
 
 impl Chart {
     fn new(data: Vec<(String, u64)>) -> Self {
         let (labels, values) = data.into_iter().collect();
-        Self { labels, values }
+        Self {
+            labels,
+            values,
+            annotations: vec![],
+        }
     }
 
     fn inc(&mut self, s: String) {
@@ -67,7 +190,8 @@ impl Chart {
     }
 
     fn inc_by(&mut self, s: String, amount: u64) {
-        match self.labels.iter().position(|l| *l == s) {
+        // points are mostly added in order, so the label is usually the last one
+        match self.labels.iter().rposition(|l| *l == s) {
             Some(i) => self.values[i] += amount,
             None => {
                 self.labels.push(s);
@@ -84,22 +208,56 @@ impl Chart {
     fn len(&self) -> usize {
         self.labels.len()
     }
+
+    /// Keeps at most `max` points, the last ones when `latest` is set and the first ones
+    /// otherwise. Returns whether any were dropped.
+    fn limit(&mut self, max: usize, latest: bool) -> bool {
+        let excess = self.len().saturating_sub(max);
+        if excess == 0 {
+            return false;
+        }
+        let range = if latest { 0..excess } else { max..self.len() };
+        self.labels.drain(range.clone());
+        self.values.drain(range.clone());
+        if !self.annotations.is_empty() {
+            self.annotations.drain(range);
+        }
+        true
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 struct Player {
     name: String,
+    /// For avatars, missing for players that are only known by name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uuid: Option<String>,
+    /// A css color derived from the name, so the player looks the same on every chart.
+    color: String,
     total_deaths: u64,
+    distinct_causes: usize,
     exclusive_deaths: Vec<String>,
     unique_deaths: Chart,
     deaths_over_time: Chart,
 }
 
+/// Hashes the name with FNV-1a, which unlike std's hasher is guaranteed to stay the same across
+/// releases, into a hue.
+fn player_color(name: &str) -> String {
+    let hash = name.bytes().fold(0xcbf29ce484222325_u64, |hash, b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x100000001b3)
+    });
+    format!("hsl({}, 65%, 50%)", hash % 360)
+}
+
 impl Player {
-    fn new(name: String) -> Self {
+    fn new(name: String, uuid: Option<String>) -> Self {
         Self {
+            color: player_color(&name),
             name,
+            uuid,
             total_deaths: 0,
+            distinct_causes: 0,
             exclusive_deaths: vec![],
             unique_deaths: Default::default(),
             deaths_over_time: Default::default(),
@@ -107,51 +265,1055 @@ impl Player {
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 struct Year {
     number: i32,
     enabled: bool,
 }
 
-#[derive(Debug, Template, Default)]
+#[derive(Debug, Clone, Template, Default, Serialize, ToSchema)]
 #[template(path = "deaths/index.html")]
 struct DeathsTemplate {
+    #[serde(skip)]
+    #[schema(ignore)]
+    base_path: String,
+    #[serde(skip)]
+    #[schema(ignore)]
+    locale: Locale,
     years: Vec<Year>,
     no_year_enabled: bool,
     total_deaths: usize,
+    unique_players: usize,
     players: Vec<Player>,
     unique_deaths: Chart,
     deaths_over_time: Chart,
+    new_players: Chart,
+    /// Deaths per [`Category`].
+    categories: Chart,
+    pagination: Option<Pagination>,
+    /// Whether players or chart points were left out because of `max_players_in_response` or
+    /// `max_chart_points`.
+    truncated: bool,
 }
 
-#[derive(Debug, Deserialize)]
+impl Page for DeathsTemplate {
+    const OVERRIDE: Option<&'static str> = Some("deaths/index.html");
+}
+
+/// Which slice of the players is being shown, the global charts always cover everyone.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+struct Pagination {
+    page: usize,
+    per_page: usize,
+    pages: usize,
+    /// The other query parameters, which every page keeps.
+    #[serde(skip)]
+    #[schema(ignore)]
+    filters: String,
+}
+
+impl Pagination {
+    /// The query string that links to another page.
+    fn query(&self, page: usize) -> String {
+        format!("page={page}&per_page={}{}", self.per_page, self.filters)
+    }
+}
+
+/// The time span deaths are restricted to, either end can be open.
+#[derive(Debug, Clone, Copy, Default)]
+struct Range {
+    from: Option<NaiveDateTime>,
+    to: Option<NaiveDateTime>,
+}
+
+impl Range {
+    fn contains(&self, timestamp: NaiveDateTime) -> bool {
+        self.from.is_none_or(|from| from <= timestamp) && self.to.is_none_or(|to| timestamp <= to)
+    }
+
+    fn is_unbounded(&self) -> bool {
+        self.from.is_none() && self.to.is_none()
+    }
+}
+
+/// Converts a time to the timezone death records are in, the display timezone or the host's when
+/// there is none.
+fn display_time(config: &Config, time: DateTime<FixedOffset>) -> NaiveDateTime {
+    match config.display_timezone {
+        Some(tz) => time.with_timezone(&tz).naive_local(),
+        None => time.with_timezone(&Local).naive_local(),
+    }
+}
+
+/// Parses a bound of a [`Range`], either a RFC 3339 time or a `YYYY-MM-DD` date. A date that
+/// ends the range includes that whole day.
+fn parse_bound(config: &Config, bound: &str, end: bool) -> Result<NaiveDateTime, Error> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(bound) {
+        return Ok(display_time(config, time));
+    }
+    let date = NaiveDate::parse_from_str(bound, "%Y-%m-%d")
+        .map_err(|e| Error::BadRequest(format!("invalid date {bound:?}: {e}")))?;
+    Ok(match end {
+        true => date.and_hms_milli_opt(23, 59, 59, 999).unwrap(),
+        false => date.and_time(NaiveTime::MIN),
+    })
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct DeathQuery {
     year: Option<i32>,
+    /// Only deaths at or after this RFC 3339 time or `YYYY-MM-DD` date.
+    from: Option<String>,
+    /// Only deaths at or before this RFC 3339 time or `YYYY-MM-DD` date.
+    to: Option<String>,
+    page: Option<usize>,
+    per_page: Option<usize>,
+    /// What the players are sorted by, `deaths` by default.
+    sort: Option<PlayerSort>,
+    /// Descending by default, except when sorting by name.
+    order: Option<SortOrder>,
+    /// Players with fewer deaths are left out of the players, they're still in the totals and
+    /// the global charts. `min_deaths` from the config by default.
+    min_deaths: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayerSort {
+    Deaths,
+    Name,
+    DistinctCauses,
+}
+
+impl PlayerSort {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Deaths => "deaths",
+            Self::Name => "name",
+            Self::DistinctCauses => "distinct_causes",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Asc => "asc",
+            Self::Desc => "desc",
+        }
+    }
+}
+
+/// Sorts the players, ties are broken by name so the order is stable across requests.
+fn sort_players(players: &mut [Player], sort: PlayerSort, order: SortOrder) {
+    players.sort_by(|a, b| {
+        let ordering = match sort {
+            PlayerSort::Deaths => a.total_deaths.cmp(&b.total_deaths),
+            PlayerSort::Name => a.name.cmp(&b.name),
+            PlayerSort::DistinctCauses => a.distinct_causes.cmp(&b.distinct_causes),
+        };
+        let ordering = match order {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        };
+        ordering.then_with(|| a.name.cmp(&b.name))
+    });
 }
 
+/// The deaths page, aggregated from the server logs. This is the only handler for `/deaths`.
+#[utoipa::path(get, path = "/deaths", params(DeathQuery), responses((status = 200, body = DeathsTemplate)))]
 pub async fn deaths(
-    config: State<Arc<Config>>,
-    Query(DeathQuery { year }): Query<DeathQuery>,
+    State(AppState {
+        config,
+        refreshed_deaths,
+        ..
+    }): State<AppState>,
+    headers: HeaderMap,
+    Query(DeathQuery {
+        year,
+        from,
+        to,
+        page,
+        per_page,
+        sort,
+        order,
+        min_deaths,
+    }): Query<DeathQuery>,
+) -> Result<Response, Error> {
+    let range = Range {
+        from: from
+            .as_deref()
+            .map(|f| parse_bound(&config, f, false))
+            .transpose()?,
+        to: to
+            .as_deref()
+            .map(|t| parse_bound(&config, t, true))
+            .transpose()?,
+    };
+    if let Range {
+        from: Some(from),
+        to: Some(to),
+    } = range
+        && from > to
+    {
+        return Err(Error::BadRequest(format!(
+            "from ({from}) is after to ({to})"
+        )));
+    }
+    let cached = match year {
+        None if range.is_unbounded() => refreshed_deaths.get(&config).await?,
+        _ => None,
+    };
+    // a cached aggregation may be older than the logs, so it's tagged with what it was made of
+    let etag = match &cached {
+        Some(cached) => cached.etag.clone(),
+        None => logs::logs_etag(&config).await,
+    }
+    .map(|etag| match wants_json(&headers) {
+        // the json and html representations need distinct etags
+        true => format!("{}-json\"", etag.trim_end_matches('"')),
+        false => etag,
+    });
+    let cache_headers = config.cache_headers(etag.as_deref());
+    if let Some(etag) = &etag
+        && etag_matches(&headers, etag)
+    {
+        return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+    }
+    let mut template = match cached {
+        Some(cached) => cached.template,
+        None => aggregate(&config, year, range).await?,
+    };
+    if let Some(min) = min_deaths.or(config.min_deaths) {
+        template.players.retain(|p| p.total_deaths >= min);
+    }
+    let by = sort.unwrap_or(PlayerSort::Deaths);
+    let direction = order.unwrap_or(match by {
+        PlayerSort::Name => SortOrder::Asc,
+        _ => SortOrder::Desc,
+    });
+    sort_players(&mut template.players, by, direction);
+    if let Some(per_page) = per_page.filter(|&n| n > 0) {
+        let page = page.unwrap_or(1).max(1);
+        let listed = template.players.len();
+        template.players = std::mem::take(&mut template.players)
+            .into_iter()
+            .skip((page - 1) * per_page)
+            .take(per_page)
+            .collect();
+        template.pagination = Some(Pagination {
+            page,
+            per_page,
+            pages: listed.div_ceil(per_page),
+            filters: [
+                ("year", year.map(|y| y.to_string())),
+                ("from", from),
+                ("to", to),
+                ("sort", sort.map(|s| s.as_str().to_owned())),
+                ("order", order.map(|o| o.as_str().to_owned())),
+                ("min_deaths", min_deaths.map(|m| m.to_string())),
+            ]
+            .into_iter()
+            .filter_map(|(key, value)| Some(format!("&{key}={}", value?.replace('+', "%2B"))))
+            .collect(),
+        });
+    }
+    if wants_json(&headers) {
+        limit_response(&config, &mut template);
+    }
+    Ok((cache_headers, negotiate(&config, &headers, template)?).into_response())
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PlayerQuery {
+    year: Option<i32>,
+}
+
+/// One player's stats, as they are in `/deaths`, for clients that don't need everyone's.
+#[utoipa::path(get, path = "/deaths/player/{name}", params(("name" = String, Path), PlayerQuery), responses((status = 200, body = Player), (status = 404)))]
+pub async fn player(
+    State(AppState {
+        config,
+        refreshed_deaths,
+        ..
+    }): State<AppState>,
+    Path(name): Path<String>,
+    Query(PlayerQuery { year }): Query<PlayerQuery>,
+) -> Result<impl IntoResponse, Error> {
+    let name = canonical_name(&config, name);
+    let player = aggregated(&config, &refreshed_deaths, year)
+        .await?
+        .players
+        .into_iter()
+        .find(|p| p.name == name)
+        .ok_or(Error::NotFound)?;
+    Ok((config.cache_headers(None), Json(player)))
+}
+
+/// The name deaths of a player are counted under, which differs from the one asked for when it's
+/// one of their old names.
+fn canonical_name(config: &Config, name: String) -> String {
+    match config
+        .player_aliases
+        .as_ref()
+        .and_then(|aliases| aliases.get(&name))
+    {
+        Some(canonical) => canonical.clone(),
+        None => name,
+    }
+}
+
+/// The aggregation of a year, or of all time from the cache.
+async fn aggregated(
+    config: &Arc<Config>,
+    refreshed_deaths: &Refreshed,
+    year: Option<i32>,
+) -> Result<DeathsTemplate, Error> {
+    let cached = match year {
+        None => refreshed_deaths.get(config).await?,
+        Some(_) => None,
+    };
+    match cached {
+        Some(cached) => Ok(cached.template),
+        None => aggregate(config, year, Range::default()).await,
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CompareQuery {
+    a: String,
+    b: String,
+    year: Option<i32>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct Comparison {
+    a: ComparedPlayer,
+    b: ComparedPlayer,
+    /// Causes both players died from, most common first.
+    shared_causes: Vec<SharedCause>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct ComparedPlayer {
+    name: String,
+    total_deaths: u64,
+    /// Causes only this player of the two died from, most common first.
+    unique_causes: Vec<CauseCount>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct SharedCause {
+    cause: String,
+    a: u64,
+    b: u64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct CauseCount {
+    cause: String,
+    deaths: u64,
+}
+
+/// Two players head to head: their totals, what both died from and what only each did.
+#[utoipa::path(get, path = "/deaths/compare", params(CompareQuery), responses((status = 200, body = Comparison), (status = 404)))]
+pub async fn compare(
+    State(AppState {
+        config,
+        refreshed_deaths,
+        ..
+    }): State<AppState>,
+    Query(CompareQuery { a, b, year }): Query<CompareQuery>,
+) -> Result<impl IntoResponse, Error> {
+    let template = aggregated(&config, &refreshed_deaths, year).await?;
+    let find = |name: String| {
+        let name = canonical_name(&config, name);
+        template
+            .players
+            .iter()
+            .find(|p| p.name == name)
+            .ok_or(Error::NotFound)
+    };
+    let (a, b) = (find(a)?, find(b)?);
+    let causes = |p: &Player| {
+        p.unique_deaths
+            .labels
+            .iter()
+            .cloned()
+            .zip(p.unique_deaths.values.iter().copied())
+            .collect::<Vec<_>>()
+    };
+    let (a_causes, b_causes) = (causes(a), causes(b));
+    let count_in = |causes: &[(String, u64)], cause: &str| {
+        causes.iter().find(|(c, _)| c == cause).map(|(_, n)| *n)
+    };
+    let unique = |causes: &[(String, u64)], other: &[(String, u64)]| {
+        causes
+            .iter()
+            .filter(|(cause, _)| count_in(other, cause).is_none())
+            .map(|(cause, deaths)| CauseCount {
+                cause: cause.clone(),
+                deaths: *deaths,
+            })
+            .collect()
+    };
+    let mut shared_causes = a_causes
+        .iter()
+        .filter_map(|(cause, a)| {
+            Some(SharedCause {
+                cause: cause.clone(),
+                a: *a,
+                b: count_in(&b_causes, cause)?,
+            })
+        })
+        .collect::<Vec<_>>();
+    shared_causes.sort_by(|x, y| {
+        (y.a + y.b)
+            .cmp(&(x.a + x.b))
+            .then_with(|| x.cause.cmp(&y.cause))
+    });
+    Ok(Json(Comparison {
+        a: ComparedPlayer {
+            name: a.name.clone(),
+            total_deaths: a.total_deaths,
+            unique_causes: unique(&a_causes, &b_causes),
+        },
+        b: ComparedPlayer {
+            name: b.name.clone(),
+            total_deaths: b.total_deaths,
+            unique_causes: unique(&b_causes, &a_causes),
+        },
+        shared_causes,
+    }))
+}
+
+/// Trims the json response to the configured size, the html is paginated instead.
+fn limit_response(config: &Config, template: &mut DeathsTemplate) {
+    if let Some(max) = config.max_players_in_response
+        && template.players.len() > max
+    {
+        template.players.truncate(max);
+        template.truncated = true;
+    }
+    if let Some(max) = config.max_chart_points {
+        // the causes are sorted by how common they are and the rest by date
+        let charts = [
+            (&mut template.unique_deaths, false),
+            (&mut template.deaths_over_time, true),
+            (&mut template.new_players, true),
+        ]
+        .into_iter()
+        .chain(template.players.iter_mut().flat_map(|p| {
+            [
+                (&mut p.unique_deaths, false),
+                (&mut p.deaths_over_time, true),
+            ]
+        }));
+        for (chart, latest) in charts {
+            template.truncated |= chart.limit(max, latest);
+        }
+    }
+}
+
+/// The all-years aggregation with when it was made, kept up to date by [`refresh`] when it's
+/// running or revalidated by `/deaths` when `deaths_stale_after_secs` is set.
+#[derive(Debug, Clone, Default)]
+pub struct Refreshed {
+    deaths: Arc<RwLock<Option<Aggregation>>>,
+    /// Whether a background aggregation is already running, so only one is started at a time.
+    revalidating: Arc<AtomicBool>,
+}
+
+/// An aggregation of all years.
+#[derive(Debug, Clone)]
+struct Aggregation {
+    aggregated_at: Instant,
+    /// The etag of the logs when it was aggregated.
+    etag: Option<String>,
+    template: DeathsTemplate,
+}
+
+impl Aggregation {
+    async fn new(config: &Arc<Config>) -> Result<Self, Error> {
+        // taken first, so lines logged while aggregating make the next etag differ
+        let etag = logs::logs_etag(config).await;
+        let template = aggregate(config, None, Range::default()).await?;
+        Ok(Self {
+            aggregated_at: Instant::now(),
+            etag,
+            template,
+        })
+    }
+}
+
+impl Refreshed {
+    async fn store(&self, aggregation: Aggregation) {
+        *self.deaths.write().await = Some(aggregation);
+    }
+
+    /// The cached aggregation. With `deaths_stale_after_secs` set the cache is filled by the
+    /// first request, and once it's stale it's still served while it's re-aggregated in the
+    /// background for the following requests.
+    async fn get(&self, config: &Arc<Config>) -> Result<Option<Aggregation>, Error> {
+        let cached = self.deaths.read().await.clone();
+        let Some(stale_after) = config.deaths_stale_after_secs.map(Duration::from_secs) else {
+            return Ok(cached);
+        };
+        let Some(cached) = cached else {
+            let aggregation = Aggregation::new(config).await?;
+            self.store(aggregation.clone()).await;
+            return Ok(Some(aggregation));
+        };
+        if cached.aggregated_at.elapsed() > stale_after
+            && !self.revalidating.swap(true, Ordering::AcqRel)
+        {
+            let (config, refreshed) = (config.clone(), self.clone());
+            tokio::spawn(async move {
+                let start = Instant::now();
+                match Aggregation::new(&config).await {
+                    Ok(aggregation) => {
+                        tracing::info!(elapsed = ?start.elapsed(), "revalidated deaths");
+                        refreshed.store(aggregation).await;
+                    }
+                    Err(e) => tracing::error!(error = ?e, "failed to revalidate deaths"),
+                }
+                refreshed.revalidating.store(false, Ordering::Release);
+            });
+        }
+        Ok(Some(cached))
+    }
+}
+
+/// Re-aggregates the deaths every `interval`, so `/deaths` never has to wait on parsing.
+pub async fn refresh(shared: SharedState, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        // with the config of the moment, which `/admin/reload` may have changed
+        let AppState {
+            config,
+            refreshed_deaths,
+            ..
+        } = AppState::from_ref(&shared);
+        let start = Instant::now();
+        match Aggregation::new(&config).await {
+            Ok(aggregation) => {
+                tracing::info!(elapsed = ?start.elapsed(), "refreshed deaths");
+                refreshed_deaths.store(aggregation).await;
+            }
+            Err(e) => tracing::error!(error = ?e, "failed to refresh deaths"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CauseQuery {
+    q: String,
+    #[serde(default)]
+    contains: bool,
+    year: Option<i32>,
+}
+
+#[utoipa::path(get, path = "/deaths/cause", params(CauseQuery), responses((status = 200, body = Vec<DeathRecord>)))]
+pub async fn cause(
+    State(AppState { config, .. }): State<AppState>,
+    Query(CauseQuery { q, contains, year }): Query<CauseQuery>,
+) -> Result<impl IntoResponse, Error> {
+    let deaths = parse_logs(config)
+        .await?
+        .filter(|d| ready(year.is_none_or(|y| d.timestamp.year() == y)))
+        .filter(|d| {
+            ready(if contains {
+                d.cause.contains(&q)
+            } else {
+                d.cause == q
+            })
+        })
+        .collect::<Vec<_>>()
+        .await;
+    Ok(Json(deaths))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RecentQuery {
+    #[param(value_type = String, format = DateTime)]
+    since: DateTime<FixedOffset>,
+    player: Option<String>,
+}
+
+/// Deaths strictly after `since`, for clients that poll for new deaths.
+#[utoipa::path(get, path = "/deaths/recent", params(RecentQuery), responses((status = 200, body = Vec<DeathRecord>)))]
+pub async fn recent(
+    State(AppState { config, .. }): State<AppState>,
+    Query(RecentQuery { since, player }): Query<RecentQuery>,
+) -> Result<impl IntoResponse, Error> {
+    let since = display_time(&config, since);
+    let player = player.map(|p| canonical_name(&config, p));
+    let deaths = parse_logs(config)
+        .await?
+        .filter(|d| ready(d.timestamp > since))
+        .filter(|d| ready(player.as_ref().is_none_or(|p| d.player == *p)))
+        .collect::<Vec<_>>()
+        .await;
+    Ok(Json(deaths))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct YearSummary {
+    year: i32,
+    total_deaths: u64,
+    top_cause: String,
+    /// Change in total deaths from the year before, left out when nobody died that year.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    delta: Option<i64>,
+    /// `delta` as a percentage of the year before's deaths.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    delta_percent: Option<f64>,
+}
+
+#[utoipa::path(get, path = "/deaths/summary", responses((status = 200, body = Vec<YearSummary>)))]
+pub async fn summary(
+    State(AppState { config, .. }): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    let mut by_year = BTreeMap::<i32, HashMap<String, u64>>::new();
+    parse_logs(config)
+        .await?
+        .for_each(|d| {
+            *by_year
+                .entry(d.timestamp.year())
+                .or_default()
+                .entry(d.cause)
+                .or_default() += 1;
+            ready(())
+        })
+        .await;
+    let totals = by_year
+        .iter()
+        .map(|(year, causes)| (*year, causes.values().sum::<u64>()))
+        .collect::<HashMap<_, _>>();
+    let summaries = by_year
+        .into_iter()
+        .map(|(year, causes)| {
+            let total_deaths = totals[&year];
+            let previous = totals.get(&(year - 1)).copied();
+            let delta = previous.map(|p| total_deaths as i64 - p as i64);
+            YearSummary {
+                year,
+                total_deaths,
+                top_cause: causes
+                    .into_iter()
+                    .max_by(|(a_cause, a), (b_cause, b)| {
+                        a.cmp(b).then_with(|| b_cause.cmp(a_cause))
+                    })
+                    .map(|(cause, _)| cause)
+                    .unwrap_or_default(),
+                delta,
+                delta_percent: delta
+                    .zip(previous)
+                    .map(|(d, p)| d as f64 / p as f64 * 100.0),
+            }
+        })
+        .collect::<Vec<_>>();
+    Ok(Json(summaries))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HeatmapQuery {
+    year: Option<i32>,
+    player: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct HeatmapCell {
+    /// Days since monday.
+    weekday: u32,
+    hour: u32,
+    count: u64,
+}
+
+/// Deaths counted by day of the week and hour of the day, one cell for each of the 7x24.
+#[utoipa::path(get, path = "/deaths/heatmap", params(HeatmapQuery), responses((status = 200, body = Vec<HeatmapCell>)))]
+pub async fn heatmap(
+    State(AppState { config, .. }): State<AppState>,
+    Query(HeatmapQuery { year, player }): Query<HeatmapQuery>,
 ) -> Result<impl IntoResponse, Error> {
-    let deaths = logs::parse_logs(&config)
+    let mut counts = [[0; 24]; 7];
+    let player = player.map(|p| canonical_name(&config, p));
+    parse_logs(config)
         .await?
-        .filter(|line| {
+        .filter(|d| {
             ready(
-                !IGNORED_MESSAGES
-                    .iter()
-                    .any(|&msg| line.message.contains(msg)),
+                year.is_none_or(|y| d.timestamp.year() == y)
+                    && player.as_ref().is_none_or(|p| d.player == *p),
             )
         })
-        .filter(|line| ready(!IGNORED_TIMESTAMPS.contains(&line.timestamp)))
+        .for_each(|d| {
+            counts[d.timestamp.weekday().num_days_from_monday() as usize]
+                [d.timestamp.hour() as usize] += 1;
+            ready(())
+        })
+        .await;
+    let cells = (0..7)
+        .flat_map(|weekday| (0..24).map(move |hour| (weekday, hour)))
+        .map(|(weekday, hour)| HeatmapCell {
+            weekday,
+            hour,
+            count: counts[weekday as usize][hour as usize],
+        })
+        .collect::<Vec<_>>();
+    Ok(Json(cells))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CalendarQuery {
+    /// The current year by default.
+    year: Option<i32>,
+    player: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct CalendarDay {
+    #[schema(value_type = String, format = Date)]
+    date: NaiveDate,
+    /// Column of the day in the grid, the week of January 1st being 0. Weeks start on monday.
+    week: u32,
+    /// Days since monday.
+    weekday: u32,
+    count: u64,
+}
+
+/// Deaths on every day of a year, including the ones nobody died on, to lay out as a calendar.
+#[utoipa::path(get, path = "/deaths/calendar", params(CalendarQuery), responses((status = 200, body = Vec<CalendarDay>)))]
+pub async fn calendar(
+    State(AppState { config, .. }): State<AppState>,
+    Query(CalendarQuery { year, player }): Query<CalendarQuery>,
+) -> Result<impl IntoResponse, Error> {
+    let year = year.unwrap_or_else(|| display_time(&config, Local::now().fixed_offset()).year());
+    let first_day = NaiveDate::from_ymd_opt(year, 1, 1)
+        .ok_or_else(|| Error::BadRequest(format!("invalid year {year}")))?;
+    let mut counts = HashMap::<NaiveDate, u64>::new();
+    let player = player.map(|p| canonical_name(&config, p));
+    parse_logs(config)
+        .await?
+        .filter(|d| {
+            ready(d.timestamp.year() == year && player.as_ref().is_none_or(|p| d.player == *p))
+        })
+        .for_each(|d| {
+            *counts.entry(d.timestamp.date()).or_default() += 1;
+            ready(())
+        })
+        .await;
+    let offset = first_day.weekday().num_days_from_monday();
+    let days = first_day
+        .iter_days()
+        .take_while(|date| date.year() == year)
+        .map(|date| CalendarDay {
+            date,
+            week: (date.ordinal0() + offset) / 7,
+            weekday: date.weekday().num_days_from_monday(),
+            count: counts.get(&date).copied().unwrap_or_default(),
+        })
+        .collect::<Vec<_>>();
+    Ok(Json(days))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PvpQuery {
+    year: Option<i32>,
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+struct PvpStats {
+    player: String,
+    kills: u64,
+    /// Deaths by any cause, not only at the hands of other players.
+    deaths: u64,
+    pvp_deaths: u64,
+    /// Kills per death, deaths are counted as at least one so a player who never died has their
+    /// kills as the ratio.
+    kill_death_ratio: f64,
+    /// Players this player killed, with how many times.
+    victims: BTreeMap<String, u64>,
+    /// Players who killed this player, with how many times.
+    killers: BTreeMap<String, u64>,
+}
+
+/// Kills and deaths between players, for every player who killed or died.
+#[utoipa::path(get, path = "/deaths/pvp", params(PvpQuery), responses((status = 200, body = Vec<PvpStats>)))]
+pub async fn pvp(
+    State(AppState { config, .. }): State<AppState>,
+    Query(PvpQuery { year }): Query<PvpQuery>,
+) -> Result<impl IntoResponse, Error> {
+    let known = logs::players(&config)?;
+    let players = known
+        .iter()
+        .flat_map(|p| std::iter::once(&p.name).chain(&p.aliases))
+        .cloned()
+        .collect::<Vec<_>>();
+    let canonical = |name: &str| {
+        known
+            .iter()
+            .find(|p| p.aliases.iter().any(|a| a == name))
+            .map_or_else(
+                || canonical_name(&config, name.to_owned()),
+                |p| p.name.clone(),
+            )
+    };
+    let deaths = parse_logs(config.clone())
+        .await?
+        .filter(|d| ready(year.is_none_or(|y| d.timestamp.year() == y)))
         .collect::<Vec<_>>()
         .await;
+    let mut stats = BTreeMap::<String, PvpStats>::new();
+    fn stats_of(stats: &mut BTreeMap<String, PvpStats>, player: String) -> &mut PvpStats {
+        stats.entry(player.clone()).or_insert_with(|| PvpStats {
+            player,
+            ..Default::default()
+        })
+    }
+    for death in deaths {
+        let killer = killer(&death.cause)
+            .filter(|k| players.iter().any(|p| p == k))
+            .map(canonical);
+        let victim = stats_of(&mut stats, death.player.clone());
+        victim.deaths += 1;
+        // a renamed player's old name can still show up in their own death message
+        let Some(killer) = killer.filter(|k| *k != death.player) else {
+            continue;
+        };
+        victim.pvp_deaths += 1;
+        *victim.killers.entry(killer.clone()).or_default() += 1;
+        let killer = stats_of(&mut stats, killer);
+        killer.kills += 1;
+        *killer.victims.entry(death.player).or_default() += 1;
+    }
+    let mut stats = stats
+        .into_values()
+        .map(|s| PvpStats {
+            kill_death_ratio: s.kills as f64 / s.deaths.max(1) as f64,
+            ..s
+        })
+        .collect::<Vec<_>>();
+    stats.sort_by(|a, b| b.kills.cmp(&a.kills).then_with(|| a.player.cmp(&b.player)));
+    Ok(Json(stats))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct Efficiency {
+    player: String,
+    deaths: u64,
+    hours_played: f64,
+    deaths_per_hour: f64,
+}
+
+/// Players ranked by how often they die for the time they spend online, most accident-prone
+/// first. Players without any recorded playtime can't be ranked and are left out.
+#[utoipa::path(get, path = "/deaths/efficiency", responses((status = 200, body = Vec<Efficiency>)))]
+pub async fn efficiency(
+    State(AppState { config, .. }): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    let mut deaths = HashMap::<String, u64>::new();
+    parse_logs(config.clone())
+        .await?
+        .for_each(|d| {
+            *deaths.entry(d.player).or_default() += 1;
+            ready(())
+        })
+        .await;
+    // deaths are counted under the canonical names
+    let mut playtime = HashMap::<String, TimeDelta>::new();
+    for (player, duration) in playtime::player_playtime(&config).await? {
+        let player = canonical_name(&config, player);
+        *playtime.entry(player).or_default() += duration;
+    }
+    let mut ranking = playtime
+        .into_iter()
+        .filter(|(_, duration)| *duration > TimeDelta::zero())
+        .map(|(player, duration)| {
+            let deaths = deaths.get(&player).copied().unwrap_or_default();
+            let hours_played = duration.num_seconds() as f64 / 3600.0;
+            Efficiency {
+                player,
+                deaths,
+                hours_played,
+                deaths_per_hour: deaths as f64 / hours_played,
+            }
+        })
+        .collect::<Vec<_>>();
+    ranking.sort_by(|a, b| {
+        b.deaths_per_hour
+            .total_cmp(&a.deaths_per_hour)
+            .then_with(|| a.player.cmp(&b.player))
+    });
+    Ok(Json(ranking))
+}
+
+/// Deaths as server-sent `death` events as soon as they're logged, for live tickers. Only deaths
+/// logged after the client connects are sent.
+pub async fn events(
+    State(AppState { config, .. }): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    let deaths = logs::tail_latest_log(&config)?
+        .filter_map(move |line| ready(death_record(&config, line)))
+        .map(|death| Event::default().event("death").json_data(death));
+    Ok(Sse::new(deaths).keep_alive(KeepAlive::default()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DebugQuery {
+    file: String,
+}
+
+/// The deaths found in a single log file, to see how the parser reads it.
+pub async fn debug(
+    State(AppState { config, .. }): State<AppState>,
+    Query(DebugQuery { file }): Query<DebugQuery>,
+) -> Result<impl IntoResponse, Error> {
+    let deaths = logs::parse_log_file(&config, &file)
+        .await?
+        .lines
+        .into_iter()
+        .filter_map(|line| death_record(&config, line))
+        .collect::<Vec<_>>();
+    Ok(Json(deaths))
+}
+
+/// Whether a message is chat, as opposed to a death message that merely mentions a `<`.
+fn is_chat(message: &str) -> bool {
+    message.starts_with('<')
+}
+
+/// Extracts the cause of death from a message, if it is a death message at all.
+fn death_cause(config: &Config, message: String) -> Option<String> {
+    match &config.death_messages {
+        Some(patterns) => patterns.iter().find_map(|re| {
+            let captures = re.captures(&message)?;
+            let cause = captures.name("cause").or_else(|| captures.get(0))?;
+            Some(cause.as_str().to_owned())
+        }),
+        None => (!is_chat(&message) && !IGNORED_MESSAGES.iter().any(|&msg| message.contains(msg)))
+            .then_some(message),
+    }
+}
+
+/// Streams every death as newline delimited JSON, without holding them all in memory.
+pub async fn export(
+    State(AppState { config, .. }): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    let deaths = parse_logs(config).await?.map(|death| {
+        serde_json::to_string(&death).map(|mut json| {
+            json.push('\n');
+            json
+        })
+    });
+    Ok((
+        [(CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(deaths),
+    ))
+}
+
+/// Deaths of all time from the cached aggregation, or 0 until there is one so the home page never
+/// waits on parsing every log.
+pub async fn total_deaths(state: &AppState) -> usize {
+    state
+        .refreshed_deaths
+        .deaths
+        .read()
+        .await
+        .as_ref()
+        .map_or(0, |cached| cached.template.total_deaths)
+}
+
+/// Every death, oldest first.
+async fn parse_logs(config: Arc<Config>) -> Result<impl Stream<Item = DeathRecord> + use<>, Error> {
+    Ok(logs::parse_logs(&config)
+        .await?
+        .filter_map(move |line| ready(death_record(&config, line))))
+}
+
+/// The death a log line reports, if any.
+fn death_record(config: &Config, line: LogLine) -> Option<DeathRecord> {
+    if IGNORED_TIMESTAMPS.contains(&line.timestamp) {
+        return None;
+    }
+    let cause = death_cause(config, line.message).filter(|cause| {
+        config
+            .ignored_causes
+            .as_ref()
+            .is_none_or(|ignored| !ignored.contains(cause))
+    })?;
+    Some(DeathRecord {
+        timestamp: match config.display_timezone {
+            Some(tz) => localize(line.timestamp, tz),
+            None => line.timestamp,
+        },
+        player: canonical_name(config, line.player),
+        uuid: line.uuid,
+        cause,
+    })
+}
+
+async fn aggregate(
+    config: &Arc<Config>,
+    year: Option<i32>,
+    range: Range,
+) -> Result<DeathsTemplate, Error> {
+    let deaths = parse_logs(config.clone()).await?.collect::<Vec<_>>().await;
 
     if deaths.is_empty() {
-        return Ok(Html(DeathsTemplate::default().render()?));
+        return Ok(DeathsTemplate {
+            base_path: config.base_path().to_owned(),
+            locale: config.locale,
+            ..Default::default()
+        });
     }
 
     let mut years = Vec::<Year>::new();
     let mut players = Vec::<Player>::new();
+    // the first and last days anyone died on
+    let span = {
+        let dates = deaths.iter().map(|d| d.timestamp.date());
+        let (first, last) = (dates.clone().min(), dates.max());
+        first.zip(last)
+    };
+
+    // first appearances are computed over all time so a returning player isn't counted as new
+    // when a year is selected
+    let new_players = {
+        let mut first_deaths = HashMap::<&str, NaiveDateTime>::new();
+        for d in &deaths {
+            first_deaths
+                .entry(&d.player)
+                .and_modify(|t| *t = (*t).min(d.timestamp))
+                .or_insert(d.timestamp);
+        }
+        let mut months = first_deaths
+            .into_values()
+            .filter(|t| year.is_none_or(|y| t.year() == y) && range.contains(*t))
+            .map(|t| t.date().with_day(1).unwrap())
+            .collect::<Vec<_>>();
+        months.sort();
+        let mut new_players = Chart::default();
+        for m in months {
+            new_players.inc(m.format("%b %Y").to_string());
+        }
+        new_players
+    };
 
     let deaths = deaths
         .iter()
@@ -170,14 +1332,14 @@ pub async fn deaths(
                 }
             }
         })
-        .filter(|d| year.is_none_or(|y| d.timestamp.year() == y))
+        .filter(|d| year.is_none_or(|y| d.timestamp.year() == y) && range.contains(d.timestamp))
         .collect::<Vec<_>>();
 
     for d in deaths.iter().rev() {
         let player = match players.iter_mut().find(|p| p.name == d.player) {
             Some(p) => p,
             None => {
-                players.push(Player::new(d.player.clone()));
+                players.push(Player::new(d.player.clone(), d.uuid.clone()));
                 players.last_mut().unwrap()
             }
         };
@@ -192,11 +1354,37 @@ pub async fn deaths(
     });
 
     let deaths_over_time = {
-        let max_date = deaths.last().unwrap().timestamp.date();
-        let mut current_date = deaths.first().unwrap().timestamp.date();
+        // converting timezones can shift deaths across day boundaries, so unless it was asked for
+        // explicitly the range has to come from the buckets themselves. An explicit one is
+        // clamped to the days anyone died on so a bound far in the future isn't filled in.
+        let first_date = range
+            .from
+            .zip(span)
+            .map(|(f, (first, _))| f.date().max(first))
+            .or_else(|| deaths_over_time_map.keys().min().copied());
+        let max_date = range
+            .to
+            .zip(span)
+            .map(|(t, (_, last))| t.date().min(last))
+            .or_else(|| deaths_over_time_map.keys().max().copied());
         let mut deaths_over_time = Chart::default();
-        while current_date <= max_date {
-            let date_key = current_date.format("%d %b %Y").to_string();
+        let (Some(first_date), Some(max_date)) = (first_date, max_date) else {
+            return Ok(DeathsTemplate {
+                base_path: config.base_path().to_owned(),
+                locale: config.locale,
+                no_year_enabled: years.iter().all(|y| !y.enabled),
+                years,
+                ..Default::default()
+            });
+        };
+        // the format is validated at startup
+        let date_format = StrftimeItems::new(config.date_label_format())
+            .parse()
+            .unwrap_or_default();
+        for current_date in first_date.iter_days().take_while(|d| *d <= max_date) {
+            let date_key = current_date
+                .format_with_items(date_format.iter())
+                .to_string();
             if let Some((dot, dead_players)) = deaths_over_time_map.get(&current_date) {
                 for dp in dead_players {
                     players
@@ -205,17 +1393,34 @@ pub async fn deaths(
                         .for_each(|p| p.deaths_over_time.inc(date_key.clone()));
                 }
                 deaths_over_time.inc_by(date_key, *dot);
+                deaths_over_time
+                    .annotations
+                    .push(deadliest(dead_players).to_owned());
             } else {
                 for p in &mut players {
                     p.deaths_over_time.add_0(date_key.clone());
                 }
                 deaths_over_time.add_0(date_key);
+                deaths_over_time.annotations.push(String::new());
             }
-            current_date = current_date.checked_add_days(Days::new(1)).unwrap();
         }
         deaths_over_time
     };
 
+    /// The player that died the most out of `dead_players`, ties go to the first alphabetically.
+    fn deadliest(dead_players: &[String]) -> &str {
+        let mut counts = BTreeMap::<&str, u64>::new();
+        for p in dead_players {
+            *counts.entry(p).or_default() += 1;
+        }
+        counts
+            .into_iter()
+            .rev()
+            .max_by_key(|(_, n)| *n)
+            .map(|(p, _)| p)
+            .unwrap_or_default()
+    }
+
     fn death_pie_chart<I>(i: I) -> Chart
     where
         I: Iterator,
@@ -234,14 +1439,38 @@ pub async fn deaths(
         Chart::new(unique_deaths)
     }
 
-    let unique_deaths = death_pie_chart(deaths.iter().map(|d| &d.message));
+    // renamed before counting so causes renamed to the same label are counted together
+    let label = |cause: &str| {
+        config
+            .cause_rename
+            .as_ref()
+            .and_then(|renames| renames.get(cause))
+            .map_or_else(|| cause.to_owned(), Clone::clone)
+    };
+    let unique_deaths = death_pie_chart(deaths.iter().map(|d| label(&d.cause)));
+    // categories come from the causes as they were logged
+    let categories = {
+        // any known player makes a death PvP, not only the ones who died themselves
+        let known = logs::players(config)?;
+        let names = known
+            .iter()
+            .flat_map(|p| std::iter::once(&p.name).chain(&p.aliases))
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        death_pie_chart(
+            deaths
+                .iter()
+                .map(|d| format!("{:?}", Category::of(&d.cause, &names))),
+        )
+    };
     for p in &mut players {
         p.unique_deaths = death_pie_chart(
             deaths
                 .iter()
                 .filter(|d| d.player == p.name)
-                .map(|d| &d.message),
+                .map(|d| label(&d.cause)),
         );
+        p.distinct_causes = p.unique_deaths.len();
     }
     for p in &mut players {
         p.exclusive_deaths = p
@@ -251,22 +1480,455 @@ pub async fn deaths(
             .filter(|pd| {
                 deaths
                     .iter()
-                    .filter(|d| d.message == **pd)
+                    .filter(|d| label(&d.cause) == **pd)
                     .all(|d| d.player == p.name)
             })
             .cloned()
             .collect();
     }
 
-    Ok(Html(
-        DeathsTemplate {
-            no_year_enabled: years.iter().all(|y| !y.enabled),
-            years,
-            total_deaths: deaths.len(),
-            players,
-            deaths_over_time,
-            unique_deaths,
+    Ok(DeathsTemplate {
+        base_path: config.base_path().to_owned(),
+        locale: config.locale,
+        no_year_enabled: years.iter().all(|y| !y.enabled),
+        years,
+        total_deaths: deaths.len(),
+        unique_players: players.len(),
+        players,
+        deaths_over_time,
+        unique_deaths,
+        new_players,
+        categories,
+        pagination: None,
+        truncated: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, ServerDir, log_line};
+    use axum::http::{StatusCode, header::ETAG};
+    use serde_json::json;
+    use std::{io::Write, time::SystemTime};
+
+    #[test]
+    fn categories_of_death_messages() {
+        let players = ["alice", "bob"];
+        for (cause, category) in [
+            ("tried to swim in lava", Category::Lava),
+            ("drowned", Category::Drowning),
+            ("starved to death", Category::Starvation),
+            ("was blown up by Creeper", Category::Explosion),
+            (
+                "was killed by [Intentional Game Design]",
+                Category::Explosion,
+            ),
+            ("burned to death", Category::Fire),
+            ("fell from a high place", Category::Fall),
+            ("hit the ground too hard", Category::Fall),
+            ("was slain by Zombie", Category::Mob),
+            ("was shot by Skeleton", Category::Mob),
+            ("was slain by Drowned", Category::Mob),
+            ("was shot by Drowned using [Trident]", Category::Mob),
+            ("was slain by Lava Slime", Category::Mob),
+            ("was blown up by bob", Category::PvP),
+            ("drowned whilst trying to escape Zombie", Category::Drowning),
+            (
+                "was killed by magic whilst trying to escape bob",
+                Category::PvP,
+            ),
+            ("was killed while trying to hurt alice", Category::PvP),
+            ("was squashed by a falling anvil", Category::Other),
+            ("was stung to death", Category::Mob),
+            ("was slain by bob", Category::PvP),
+            ("was shot by alice using [Bow]", Category::PvP),
+            ("was slain by carol", Category::Mob),
+            ("died", Category::Other),
+        ] {
+            assert_eq!(Category::of(cause, &players), category, "{cause}");
         }
-        .render()?,
-    ))
+    }
+
+    #[tokio::test]
+    async fn renamed_causes_are_counted_together() {
+        let server = server(
+            &["alice", "bob"],
+            &[
+                ("05Jun2025 10:00:00.000", "alice was pricked to death"),
+                (
+                    "05Jun2025 10:01:00.000",
+                    "bob was poked to death by a sweet berry bush",
+                ),
+                ("05Jun2025 10:02:00.000", "bob drowned"),
+            ],
+        );
+        let config = server.config(json!({ "cause_rename": {
+            "was pricked to death": "plants",
+            "was poked to death by a sweet berry bush": "plants",
+            "drowned": "fell in the water",
+        } }));
+        let (_, body) = server.get(config, "/deaths").await;
+        assert_eq!(
+            body["unique_deaths"]["labels"],
+            json!(["plants", "fell in the water"])
+        );
+        assert_eq!(body["unique_deaths"]["values"], json!([2, 1]));
+        assert_eq!(body["categories"]["labels"], json!(["Other", "Drowning"]));
+        let players = body["players"].as_array().unwrap();
+        let bob = players.iter().find(|p| p["name"] == "bob");
+        assert_eq!(bob.unwrap()["distinct_causes"], 2);
+    }
+
+    #[tokio::test]
+    async fn killers_who_never_died_are_players() {
+        let server = server(
+            &["alice", "bob"],
+            &[("05Jun2025 10:00:00.000", "alice was slain by bob")],
+        );
+        let (_, body) = server.get(server.config(json!({})), "/deaths").await;
+        assert_eq!(body["categories"]["labels"], json!(["PvP"]));
+    }
+
+    /// A server whose only log is a `latest.log` with `lines`, given as timestamp and message.
+    fn server(players: &[&str], lines: &[(&str, &str)]) -> ServerDir {
+        let server = ServerDir::new();
+        let log = lines
+            .iter()
+            .map(|(timestamp, message)| log_line(timestamp, message))
+            .collect::<String>();
+        server.whitelist(players).write("logs/latest.log", log);
+        server
+    }
+
+    #[tokio::test]
+    async fn explicit_range_is_clamped_to_the_deaths() {
+        let server = server(
+            &["alice"],
+            &[
+                ("05Jun2025 10:00:00.000", "alice fell from a high place"),
+                ("08Jun2025 10:00:00.000", "alice drowned"),
+            ],
+        );
+        let (status, body) = server
+            .get(
+                server.config(json!({})),
+                "/deaths?from=0001-01-01&to=%2B262142-12-31",
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body["deaths_over_time"]["labels"],
+            json!(["05 Jun 2025", "06 Jun 2025", "07 Jun 2025", "08 Jun 2025"])
+        );
+        assert_eq!(body["deaths_over_time"]["values"], json!([1, 0, 0, 1]));
+    }
+
+    #[tokio::test]
+    async fn old_names_roll_into_the_canonical_player() {
+        let server = ServerDir::new();
+        let latest = log_line("07Jun2025 12:00:00.000", "alice fell from a high place");
+        server
+            .whitelist(&["alice"])
+            .write_gz(
+                "logs/2025-06-05-1.log.gz",
+                log_line("05Jun2025 10:00:00.000", "ally drowned"),
+            )
+            .write_gz("logs/2025-06-07-1.log.gz", &latest)
+            .write("logs/latest.log", &latest);
+        let config = server.config(json!({ "player_aliases": { "ally": "alice" } }));
+        let (status, body) = server.get(config, "/deaths").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total_deaths"], 2);
+        assert_eq!(body["unique_players"], 1);
+        assert_eq!(body["players"][0]["name"], "alice");
+        assert_eq!(body["players"][0]["total_deaths"], 2);
+        assert_eq!(body["unique_deaths"]["values"], json!([1, 1]));
+        assert_eq!(body["deaths_over_time"]["values"], json!([1, 0, 1]));
+
+        // and asking for the old name gets the canonical player's deaths
+        let config = server.config(json!({ "player_aliases": { "ally": "alice" } }));
+        let (_, body) = server.get(config, "/deaths/heatmap?player=ally").await;
+        let counts = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["count"].as_u64().unwrap());
+        assert_eq!(counts.sum::<u64>(), 2);
+        let config = server.config(json!({ "player_aliases": { "ally": "alice" } }));
+        let (_, body) = server
+            .get(config, "/deaths/calendar?year=2025&player=ally")
+            .await;
+        let counts = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["count"].as_u64().unwrap());
+        assert_eq!(counts.sum::<u64>(), 2);
+    }
+
+    #[tokio::test]
+    async fn cached_deaths_keep_the_etag_they_were_aggregated_with() {
+        let server = server(
+            &["alice"],
+            &[("05Jun2025 10:00:00.000", "alice fell from a high place")],
+        );
+        let shared = testing::state(server.config(json!({ "deaths_stale_after_secs": 3600 })));
+        let etag = |response: axum::response::Response| response.headers()[ETAG].clone();
+        let cached = etag(testing::request(shared.clone(), "/deaths").await);
+
+        let latest = server.path().join("logs/latest.log");
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&latest)
+            .unwrap();
+        file.write_all(log_line("05Jun2025 11:00:00.000", "alice drowned").as_bytes())
+            .unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+
+        assert_eq!(
+            etag(testing::request(shared.clone(), "/deaths").await),
+            cached
+        );
+        let (_, body) = testing::get(shared, "/deaths").await;
+        assert_eq!(body["total_deaths"], 1);
+    }
+
+    #[tokio::test]
+    async fn new_players_are_counted_in_the_month_of_their_first_death() {
+        let server = server(
+            &["alice", "bob", "carol"],
+            &[
+                ("05Jun2025 10:00:00.000", "alice drowned"),
+                ("02Jul2025 10:00:00.000", "alice fell from a high place"),
+                ("03Jul2025 10:00:00.000", "bob drowned"),
+                ("09Jul2025 10:00:00.000", "carol drowned"),
+                ("01Aug2025 10:00:00.000", "bob drowned"),
+            ],
+        );
+        let (_, body) = server.get(server.config(json!({})), "/deaths").await;
+        assert_eq!(
+            body["new_players"]["labels"],
+            json!(["Jun 2025", "Jul 2025"])
+        );
+        assert_eq!(body["new_players"]["values"], json!([1, 2]));
+    }
+
+    #[tokio::test]
+    async fn distinct_causes_count_each_cause_once() {
+        let server = server(
+            &["alice", "bob"],
+            &[
+                ("05Jun2025 10:00:00.000", "alice drowned"),
+                ("05Jun2025 11:00:00.000", "alice drowned"),
+                ("05Jun2025 12:00:00.000", "alice drowned"),
+                ("05Jun2025 10:00:00.000", "bob drowned"),
+                ("05Jun2025 11:00:00.000", "bob fell from a high place"),
+                ("05Jun2025 12:00:00.000", "bob was slain by Zombie"),
+            ],
+        );
+        let (_, body) = server
+            .get(server.config(json!({})), "/deaths?sort=distinct_causes")
+            .await;
+        let players = body["players"].as_array().unwrap();
+        let distinct = players
+            .iter()
+            .map(|p| {
+                (
+                    p["name"].as_str().unwrap(),
+                    p["distinct_causes"].as_u64().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(distinct, [("bob", 3), ("alice", 1)]);
+    }
+
+    #[test]
+    fn death_messages_mentioning_a_less_than_are_not_chat() {
+        let server = ServerDir::new();
+        let config = server.config(json!({}));
+        let cause = |message: &str| death_cause(&config, message.to_owned());
+        assert!(is_chat("<bob> gg"));
+        assert!(!is_chat("was slain by <Bob's pet>"));
+        assert_eq!(
+            cause("was slain by <Bob's pet>").as_deref(),
+            Some("was slain by <Bob's pet>")
+        );
+        assert_eq!(cause("<bob> was slain by a zombie lol"), None);
+    }
+
+    #[tokio::test]
+    async fn ignored_players_contribute_nothing() {
+        let server = server(
+            &["alice", "bot"],
+            &[
+                ("05Jun2025 10:00:00.000", "alice drowned"),
+                ("05Jun2025 11:00:00.000", "bot fell out of the world"),
+                ("06Jun2025 11:00:00.000", "bot fell out of the world"),
+            ],
+        );
+        let config = server.config(json!({ "ignored_players": ["bot"] }));
+        let (_, body) = server.get(config, "/deaths").await;
+        assert_eq!(body["total_deaths"], 1);
+        assert_eq!(body["unique_players"], 1);
+        assert_eq!(body["players"][0]["name"], "alice");
+        assert_eq!(body["unique_deaths"]["labels"], json!(["drowned"]));
+        assert_eq!(body["deaths_over_time"]["labels"], json!(["05 Jun 2025"]));
+        assert_eq!(body["new_players"]["values"], json!([1]));
+    }
+
+    #[test]
+    fn ignored_causes_are_not_deaths() {
+        let server = ServerDir::new();
+        let config = server.config(json!({ "ignored_causes": ["was killed by /kill"] }));
+        let record = |message: &str| {
+            death_record(
+                &config,
+                LogLine {
+                    player: "alice".to_owned(),
+                    uuid: None,
+                    timestamp: "2025-06-05T10:00:00".parse().unwrap(),
+                    message: message.to_owned(),
+                },
+            )
+            .map(|d| d.cause)
+        };
+        assert_eq!(record("was killed by /kill"), None);
+        // only the exact cause is ignored
+        assert_eq!(
+            record("was killed by /kill using [Stick]").as_deref(),
+            Some("was killed by /kill using [Stick]")
+        );
+        assert_eq!(record("drowned").as_deref(), Some("drowned"));
+    }
+
+    #[tokio::test]
+    async fn heatmap_counts_deaths_by_weekday_and_hour() {
+        let server = server(
+            &["alice", "bob"],
+            &[
+                // a thursday
+                ("05Jun2025 10:05:00.000", "alice drowned"),
+                ("05Jun2025 10:55:00.000", "bob drowned"),
+                ("05Jun2025 11:00:00.000", "bob drowned"),
+                // a sunday
+                ("08Jun2025 23:59:59.000", "alice drowned"),
+            ],
+        );
+        let (_, body) = server
+            .get(server.config(json!({})), "/deaths/heatmap")
+            .await;
+        let cells = body.as_array().unwrap();
+        assert_eq!(cells.len(), 7 * 24);
+        let count = |weekday: u64, hour: u64| {
+            let cell = cells
+                .iter()
+                .find(|c| c["weekday"] == weekday && c["hour"] == hour)
+                .unwrap();
+            cell["count"].as_u64().unwrap()
+        };
+        assert_eq!(count(3, 10), 2);
+        assert_eq!(count(3, 11), 1);
+        assert_eq!(count(6, 23), 1);
+        assert_eq!(count(0, 0), 0);
+        let (_, body) = server
+            .get(server.config(json!({})), "/deaths/heatmap?player=bob")
+            .await;
+        let total = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["count"].as_u64().unwrap());
+        assert_eq!(total.sum::<u64>(), 2);
+    }
+
+    #[test]
+    fn player_colors_are_stable() {
+        // pinned so a change of hash, which would recolor everyone, doesn't go unnoticed
+        assert_eq!(player_color("alice"), "hsl(23, 65%, 50%)");
+        assert_eq!(player_color("bob"), "hsl(252, 65%, 50%)");
+        assert_eq!(player_color("Notch"), "hsl(257, 65%, 50%)");
+    }
+
+    #[tokio::test]
+    async fn min_deaths_hides_players_below_it() {
+        let server = server(
+            &["alice", "bob"],
+            &[
+                ("05Jun2025 10:00:00.000", "alice drowned"),
+                ("05Jun2025 11:00:00.000", "alice drowned"),
+                ("05Jun2025 12:00:00.000", "bob drowned"),
+            ],
+        );
+        let names = |body: serde_json::Value| {
+            body["players"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|p| p["name"].as_str().unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+        let (_, body) = server
+            .get(server.config(json!({ "min_deaths": 2 })), "/deaths")
+            .await;
+        assert_eq!(names(body.clone()), ["alice"]);
+        // the global charts still count everyone
+        assert_eq!(body["total_deaths"], 3);
+        // the query overrides the config
+        let (_, body) = server
+            .get(
+                server.config(json!({ "min_deaths": 2 })),
+                "/deaths?min_deaths=1",
+            )
+            .await;
+        assert_eq!(names(body), ["alice", "bob"]);
+    }
+
+    #[tokio::test]
+    async fn pvp_blames_the_same_killers_as_the_categories() {
+        let server = server(
+            &["alice", "bob"],
+            &[
+                ("05Jun2025 10:00:00.000", "alice was blown up by bob"),
+                (
+                    "05Jun2025 11:00:00.000",
+                    "alice drowned whilst trying to escape bob",
+                ),
+                // bob is only mentioned, not blamed
+                ("05Jun2025 12:00:00.000", "alice was hurt as bob watched"),
+            ],
+        );
+        let (_, body) = server.get(server.config(json!({})), "/deaths/pvp").await;
+        let stats = body.as_array().unwrap();
+        let alice = stats.iter().find(|s| s["player"] == "alice").unwrap();
+        assert_eq!(alice["deaths"], 3);
+        assert_eq!(alice["killers"], json!({ "bob": 2 }));
+        let (_, body) = server.get(server.config(json!({})), "/deaths").await;
+        assert_eq!(body["categories"]["labels"], json!(["PvP", "Other"]));
+        assert_eq!(body["categories"]["values"], json!([2, 1]));
+    }
+
+    #[tokio::test]
+    async fn etag_changes_with_the_rotated_logs_and_the_config() {
+        let server = server(
+            &["alice", "bob"],
+            &[("05Jun2025 10:00:00.000", "alice drowned")],
+        );
+        let etag = async |config| {
+            let response = testing::request(testing::state(config), "/deaths").await;
+            response.headers()[ETAG].clone()
+        };
+        let first = etag(server.config(json!({}))).await;
+        assert_eq!(etag(server.config(json!({}))).await, first);
+        assert_ne!(
+            etag(server.config(json!({ "ignored_players": ["bob"] }))).await,
+            first
+        );
+        server.write_gz(
+            "logs/2025-06-04-1.log.gz",
+            log_line("04Jun2025 10:00:00.000", "bob drowned"),
+        );
+        assert_ne!(etag(server.config(json!({}))).await, first);
+    }
 }
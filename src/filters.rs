@@ -0,0 +1,73 @@
+//! Custom askama filters shared by the templates.
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+
+/// How numbers are written on the pages, the json and chart data always have plain numbers.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    /// `1234567`
+    #[default]
+    Plain,
+    /// `1,234,567`
+    En,
+    /// `1.234.567`
+    De,
+    /// `1 234 567`
+    Fr,
+}
+
+impl Locale {
+    fn thousands_separator(self) -> Option<char> {
+        match self {
+            Self::Plain => None,
+            Self::En => Some(','),
+            Self::De => Some('.'),
+            Self::Fr => Some('\u{202f}'),
+        }
+    }
+}
+
+/// Writes an integer with the thousands separator of `locale`.
+pub fn separated<T: ToString>(
+    n: T,
+    _: &dyn askama::Values,
+    locale: &Locale,
+) -> askama::Result<String> {
+    let digits = n.to_string();
+    let Some(separator) = locale.thousands_separator() else {
+        return Ok(digits);
+    };
+    let (sign, digits) = match digits.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", digits.as_str()),
+    };
+    let mut out = String::from(sign);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            out.push(separator);
+        }
+        out.push(c);
+    }
+    Ok(out)
+}
+
+/// Writes a number of bytes with a binary unit, e.g. `1.5 MiB`.
+pub fn bytes<T: Borrow<usize>>(n: T, _: &dyn askama::Values) -> askama::Result<String> {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    let n = *n.borrow();
+    if n < 1024 {
+        return Ok(format!("{n} B"));
+    }
+    let mut size = n as f64 / 1024.0;
+    let mut unit = UNITS[0];
+    for next in &UNITS[1..] {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = next;
+    }
+    Ok(format!("{size:.1} {unit}"))
+}
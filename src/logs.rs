@@ -1,48 +1,166 @@
-use crate::{Config, Error};
-use chrono::NaiveDateTime;
+use crate::{AppState, CacheCounters, CacheStats, Config, Error};
+use axum::{Json, body::Body, extract::State, http::header::CONTENT_TYPE, response::IntoResponse};
+use chrono::{Days, Local, NaiveDate, NaiveDateTime, TimeZone};
 use flate2::bufread::GzDecoder;
 use futures::{Stream, StreamExt};
-use serde::Deserialize;
+use regex::Regex;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use sha2::{Digest as _, Sha256};
 use std::{
     collections::HashMap,
     fs::File,
-    io::{self, Read},
-    path::PathBuf,
+    io::{self, BufRead, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
     sync::{Arc, LazyLock},
+    time::{Duration, UNIX_EPOCH},
 };
 use tokio::sync::Mutex;
+use utoipa::ToSchema;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct WhitelistEntry {
     pub name: String,
+    pub uuid: Option<String>,
+    /// Previous names of this player, their lines are attributed to `name`.
+    #[serde(skip)]
+    pub aliases: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UserCacheEntry {
+    name: String,
+    uuid: String,
+    expires_on: String,
+}
+
+/// Merges the usercache into the whitelist. The most recently seen name of each player becomes
+/// the canonical one and any other name becomes an alias.
+fn merge_usercache(whitelist: &mut Vec<WhitelistEntry>, mut usercache: Vec<UserCacheEntry>) {
+    usercache.sort_by(|a, b| a.expires_on.cmp(&b.expires_on));
+    for UserCacheEntry { name, uuid, .. } in usercache {
+        match whitelist
+            .iter_mut()
+            .find(|e| e.uuid.as_deref() == Some(&uuid))
+        {
+            Some(entry) if entry.name != name => {
+                let old = std::mem::replace(&mut entry.name, name);
+                entry.aliases.retain(|a| *a != entry.name);
+                entry.aliases.push(old);
+            }
+            Some(_) => {}
+            None => whitelist.push(WhitelistEntry {
+                name,
+                uuid: Some(uuid),
+                aliases: vec![],
+            }),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct LogLine {
     pub player: String,
+    /// The player's uuid, when the whitelist or usercache has it.
+    pub uuid: Option<String>,
     pub timestamp: NaiveDateTime,
     pub message: String,
 }
 
+/// What was logged by the server while it was starting up.
+const SERVER_START_MESSAGE: &str = "Starting minecraft server version";
+
+/// Everything extracted from a single log file.
+#[derive(Debug, Clone, Default)]
+pub struct ParsedLog {
+    pub lines: Vec<LogLine>,
+    /// Times at which the server (re)started.
+    pub server_starts: Vec<NaiveDateTime>,
+}
+
+/// Timestamp formats logs are written with, the one of each file is whichever parses its first
+/// timestamp.
+const TIMESTAMP_FORMATS: &[&str] = &[
+    // 06Jun2025 15:42:05.682, neoforge's default
+    "%d%b%Y %H:%M:%S%.f",
+    // 2025-06-06T15:42:05.682, log4j's ISO8601
+    "%Y-%m-%dT%H:%M:%S%.f",
+    // 2025-06-06 15:42:05.682, log4j's DEFAULT
+    "%Y-%m-%d %H:%M:%S%.f",
+    // 06Jun2025 03:42:05.682 PM
+    "%d%b%Y %I:%M:%S%.f %p",
+];
+
+/// Parses a timestamp with the file's format, detecting it when it isn't known yet.
+fn parse_timestamp(
+    timestamp: &str,
+    format: &mut Option<&'static str>,
+) -> Result<NaiveDateTime, chrono::ParseError> {
+    // log4j separates the milliseconds with a comma
+    let timestamp = timestamp.replace(',', ".");
+    if let Some(format) = format {
+        return NaiveDateTime::parse_from_str(&timestamp, format);
+    }
+    let mut error = None;
+    for candidate in TIMESTAMP_FORMATS {
+        match NaiveDateTime::parse_from_str(&timestamp, candidate) {
+            Ok(parsed) => {
+                *format = Some(candidate);
+                return Ok(parsed);
+            }
+            Err(e) => error = Some(e),
+        }
+    }
+    Err(error.expect("there are timestamp formats"))
+}
+
+/// Splits a line into its bracketed metadata, starting with the timestamp, and its message.
+/// Minecraft ends the metadata with `]: `, some wrappers with `]:` or `] `.
+fn split_line(line: &str) -> Option<(&str, &str)> {
+    if let Some(end) = line.find("]: ") {
+        return Some((&line[..=end], &line[end + 3..]));
+    }
+    // without the colon the metadata is every bracketed part at the start of the line
+    let mut start = 0;
+    loop {
+        let close = start + line[start..].strip_prefix('[')?.find(']')? + 1;
+        let after = &line[close + 1..];
+        if let Some(message) = after.strip_prefix(':') {
+            return Some((&line[..=close], message.trim_start()));
+        }
+        match after.strip_prefix(' ') {
+            Some(next) if next.starts_with('[') => start = close + 2,
+            Some(message) => return Some((&line[..=close], message)),
+            None => return None,
+        }
+    }
+}
+
 #[tracing::instrument(skip_all)]
-fn parse_log(log: &str, whitelist: &[WhitelistEntry]) -> Vec<LogLine> {
+fn parse_log(log: &str, whitelist: &[WhitelistEntry], noise: &[Regex]) -> ParsedLog {
     tracing::info!("parsing log");
     let mut death_records = Vec::new();
+    let mut server_starts = Vec::new();
+    let mut timestamp_format = None;
     for line in log.lines() {
-        // Split by the standard Minecraft log separator "]: "
-        let parts: Vec<&str> = line.splitn(2, "]: ").collect();
-        if parts.len() == 2 {
-            let meta_info = parts[0];
-            let content = parts[1];
-
-            // Extract Timestamp
-            let timestamp_parts: Vec<&str> = meta_info.split_whitespace().collect();
-            let timestamp = if timestamp_parts.len() >= 2 {
-                format!("{} {}", timestamp_parts[0], timestamp_parts[1]).replace(['[', ']'], "")
-            } else {
-                "unknown".to_string()
+        if let Some((meta_info, content)) = split_line(line) {
+            // Extract Timestamp, the first bracketed part of the line
+            let timestamp = match meta_info
+                .strip_prefix('[')
+                .and_then(|meta| meta.split_once(']'))
+            {
+                Some((timestamp, _)) => timestamp.to_string(),
+                None => {
+                    let timestamp_parts: Vec<&str> = meta_info.split_whitespace().collect();
+                    if timestamp_parts.len() >= 2 {
+                        format!("{} {}", timestamp_parts[0], timestamp_parts[1])
+                            .replace(['[', ']'], "")
+                    } else {
+                        "unknown".to_string()
+                    }
+                }
             };
-            let timestamp = match NaiveDateTime::parse_from_str(&timestamp, "%d%b%Y %H:%M:%S%.f") {
+            let timestamp = match parse_timestamp(&timestamp, &mut timestamp_format) {
                 Ok(d) => d,
                 Err(e) => {
                     tracing::error!(error = ?e, timestamp, "failed to parse log timestamp");
@@ -50,102 +168,984 @@ fn parse_log(log: &str, whitelist: &[WhitelistEntry]) -> Vec<LogLine> {
                 }
             };
 
+            if content.starts_with(SERVER_START_MESSAGE) {
+                server_starts.push(timestamp);
+                continue;
+            }
+
+            if noise.iter().any(|pattern| pattern.is_match(content)) {
+                continue;
+            }
+
             // Check against known players
-            for WhitelistEntry { name } in whitelist {
-                let player_prefix = format!("{name} ");
-                if content.starts_with(&player_prefix) {
-                    let message = content[name.len()..].trim().to_string();
-                    death_records.push(LogLine {
-                        timestamp,
-                        player: name.clone(),
-                        message,
-                    });
-                    break;
-                }
+            let known = whitelist.iter().find_map(
+                |WhitelistEntry {
+                     name,
+                     uuid,
+                     aliases,
+                 }| {
+                    std::iter::once(name)
+                        .chain(aliases)
+                        .find(|alias| content.starts_with(&format!("{alias} ")))
+                        .map(|alias| (name, uuid, alias))
+                },
+            );
+            if let Some((name, uuid, alias)) = known {
+                let message = content[alias.len()..].trim().to_string();
+                death_records.push(LogLine {
+                    timestamp,
+                    player: name.clone(),
+                    uuid: uuid.clone(),
+                    message,
+                });
             }
         }
     }
-    death_records
+    ParsedLog {
+        lines: death_records,
+        server_starts,
+    }
 }
 
-/// The main parsing function
-pub async fn parse_logs(config: &Config) -> Result<impl Stream<Item = LogLine>, Error> {
-    static LOG_CACHE: LazyLock<Mutex<HashMap<PathBuf, Vec<LogLine>>>> =
-        LazyLock::new(Default::default);
+/// Reads a json list of players. A missing or empty file, as on a brand new server, is an empty
+/// list.
+fn read_player_list<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>, Error> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            tracing::warn!(?path, "player list not found");
+            return Ok(vec![]);
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if contents.trim().is_empty() {
+        return Ok(vec![]);
+    }
+    serde_json::from_str(&contents).map_err(|source| Error::InvalidJson {
+        path: path.to_owned(),
+        source,
+    })
+}
 
-    let whitelist_path = config.server_dir.join("whitelist.json");
-    tracing::debug!(?whitelist_path, "opening whitelist");
-    let whitelist: Arc<Vec<WhitelistEntry>> =
-        Arc::new(serde_json::from_reader(File::open(whitelist_path)?)?);
+/// The date embedded in a rotated log's file name, e.g. `2024-01-02-1.log.gz`.
+fn log_file_date(path: &Path) -> Option<NaiveDate> {
+    let name = path.file_name()?.to_str()?;
+    NaiveDate::parse_from_str(name.get(..10)?, "%Y-%m-%d").ok()
+}
 
+/// A weak etag of everything the deaths are read from: the modification time of `latest.log`,
+/// which is the only log that changes while the server is running, which rotated logs there
+/// are, the modification times of the player lists and the config, which decides how they're
+/// all read.
+pub async fn logs_etag(config: &Config) -> Option<String> {
+    let modified = async |path: PathBuf| {
+        let modified = tokio::fs::metadata(path).await.ok()?.modified().ok()?;
+        Some(modified.duration_since(UNIX_EPOCH).ok()?.as_millis())
+    };
     let logs_dir = config.server_dir.join("logs");
-    tracing::debug!(?logs_dir, "globing logs");
+    let mut hasher = Sha256::new();
+    // through a value, whose maps are sorted unlike the config's
+    hasher.update(serde_json::to_vec(&serde_json::to_value(config).ok()?).ok()?);
+    for file in ["whitelist.json", "ops.json", "usercache.json"] {
+        let modified = modified(config.server_dir.join(file)).await;
+        hasher.update(format!("{file} {modified:?}\0"));
+    }
+    let latest = modified(logs_dir.join("latest.log")).await;
+    hasher.update(format!("latest.log {latest:?}\0"));
+    for rotated in rotated_log_files(&logs_dir).ok()? {
+        hasher.update(rotated.as_os_str().as_encoded_bytes());
+        hasher.update([0]);
+    }
+    let hash = hasher.finalize();
+    let hex = hash[..16]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>();
+    Some(format!("W/\"{hex}\""))
+}
+
+/// Logs that were fully parsed, which never change once rotated, by their path in their
+/// [`LogSource`].
+pub struct LogCache {
+    logs: Mutex<HashMap<PathBuf, ParsedLog>>,
+    counters: CacheCounters,
+}
+
+impl Default for LogCache {
+    fn default() -> Self {
+        Self {
+            logs: Default::default(),
+            counters: CacheCounters::new(),
+        }
+    }
+}
 
-    // Collect and sort files similar to glob.glob() + sort()
-    let mut files: Vec<std::path::PathBuf> = glob::glob(&format!("{}/*.gz", logs_dir.display()))
+/// The cache of the server's logs directory.
+static LOG_CACHE: LazyLock<LogCache> = LazyLock::new(Default::default);
+
+pub async fn cache_stats() -> CacheStats {
+    LOG_CACHE.counters.stats(LOG_CACHE.logs.lock().await.len())
+}
+
+/// Forgets every parsed log, which were parsed with the players and noise of the config of the
+/// moment.
+pub async fn clear_cache() {
+    LOG_CACHE.logs.lock().await.clear();
+}
+
+/// Sorts logs oldest first, by the date in their name and then by their path.
+fn sort_logs(files: &mut [PathBuf]) {
+    files.sort_by(|a, b| {
+        log_file_date(a)
+            .cmp(&log_file_date(b))
+            .then_with(|| a.cmp(b))
+    });
+}
+
+/// The rotated logs, oldest first. Subdirectories are searched too since some server wrappers
+/// archive logs in dated folders, and the logs may or may not be compressed.
+fn rotated_log_files(logs_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let latest = logs_dir.join("latest.log");
+    let mut files: Vec<PathBuf> = glob::glob(&format!("{}/**/*", logs_dir.display()))
         .map_err(io::Error::other)?
         .collect::<Result<_, _>>()
         .map_err(io::Error::other)?;
-    files.sort();
-    files.pop(); // this one is the same as lattest.log so we don't want to cache it
+    files.retain(|f| {
+        let name = f.file_name().unwrap_or_default().to_string_lossy();
+        *f != latest && f.is_file() && (name.contains(".log") || name.ends_with(".gz"))
+    });
+    sort_logs(&mut files);
+    Ok(files)
+}
+
+/// Reads a log, decompressing it if it's gzipped.
+fn read_log(log: impl Read, contents: &mut Vec<u8>) -> io::Result<usize> {
+    let mut reader = io::BufReader::new(log);
+    let gzipped = reader.fill_buf()?.starts_with(&[0x1f, 0x8b]);
+    if gzipped {
+        GzDecoder::new(reader).read_to_end(contents)
+    } else {
+        reader.read_to_end(contents)
+    }
+}
+
+/// An open log, seekable so `latest.log` can be tailed.
+pub trait LogReader: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> LogReader for T {}
+
+/// Where logs are read from, so parsing them doesn't have to touch the disk.
+pub trait LogSource: Send + Sync + 'static {
+    /// The rotated logs, oldest first.
+    fn rotated(&self) -> io::Result<Vec<PathBuf>>;
+    /// The log the server is currently writing to.
+    fn latest(&self) -> PathBuf;
+    fn open(&self, path: &Path) -> io::Result<Box<dyn LogReader>>;
+    /// Where the rotated logs of this source are kept once parsed.
+    fn cache(&self) -> &LogCache;
+}
+
+/// The logs directory of a server.
+pub struct LogsDir(pub PathBuf);
+
+impl LogSource for LogsDir {
+    fn rotated(&self) -> io::Result<Vec<PathBuf>> {
+        rotated_log_files(&self.0)
+    }
+
+    fn latest(&self) -> PathBuf {
+        self.0.join("latest.log")
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn LogReader>> {
+        Ok(Box::new(File::open(path)?))
+    }
+
+    fn cache(&self) -> &LogCache {
+        &LOG_CACHE
+    }
+}
+
+/// Logs held in memory by path, with a cache of their own, so parsing can be tested without
+/// temp dirs.
+#[cfg(test)]
+pub struct InMemoryLogs {
+    logs: std::sync::Mutex<HashMap<PathBuf, Vec<u8>>>,
+    cache: LogCache,
+}
+
+#[cfg(test)]
+impl InMemoryLogs {
+    /// The latest log is the one at `latest.log`, any other is a rotated log.
+    pub fn new<'a>(logs: impl IntoIterator<Item = (&'a str, Vec<u8>)>) -> Self {
+        Self {
+            logs: std::sync::Mutex::new(
+                logs.into_iter()
+                    .map(|(path, contents)| (PathBuf::from(path), contents))
+                    .collect(),
+            ),
+            cache: LogCache::default(),
+        }
+    }
+
+    /// Appends to a log as the server would, creating it if it's missing.
+    pub fn append(&self, path: &str, contents: &[u8]) {
+        self.logs
+            .lock()
+            .unwrap()
+            .entry(PathBuf::from(path))
+            .or_default()
+            .extend_from_slice(contents);
+    }
+}
+
+#[cfg(test)]
+impl LogSource for InMemoryLogs {
+    fn rotated(&self) -> io::Result<Vec<PathBuf>> {
+        let latest = self.latest();
+        let mut files = self
+            .logs
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|path| **path != latest)
+            .cloned()
+            .collect::<Vec<_>>();
+        sort_logs(&mut files);
+        Ok(files)
+    }
+
+    fn latest(&self) -> PathBuf {
+        PathBuf::from("latest.log")
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn LogReader>> {
+        match self.logs.lock().unwrap().get(path) {
+            Some(contents) => Ok(Box::new(io::Cursor::new(contents.clone()))),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn cache(&self) -> &LogCache {
+        &self.cache
+    }
+}
+
+/// Whether a log is one of the debug logs, judging by its file name only so a directory named
+/// `debug` doesn't hide everything in it.
+fn is_debug_log(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().contains("debug"))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct LogFile {
+    name: String,
+    size: u64,
+    date: Option<NaiveDate>,
+    cached: bool,
+}
+
+/// Lists the log files the parser reads, newest first.
+#[utoipa::path(get, path = "/logs/files", responses((status = 200, body = Vec<LogFile>)))]
+pub async fn files(
+    State(AppState { config, .. }): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    let logs_dir = config.server_dir.join("logs");
+    let latest = logs_dir.join("latest.log");
+    let has_latest = tokio::fs::try_exists(&latest).await?;
+    let mut paths = parsed_rotated_logs(
+        rotated_log_files(&logs_dir)?,
+        has_latest,
+        log_cutoff(&config),
+        config.include_debug_logs,
+    );
+    if has_latest {
+        paths.push(latest);
+    }
+    let mut listed = Vec::with_capacity(paths.len());
+    for path in paths.into_iter().rev() {
+        if let Ok(metadata) = tokio::fs::metadata(&path).await {
+            listed.push((path, metadata.len()));
+        }
+    }
+    // locked only once the listing is done, so parsing isn't held up by it
+    let cache = LOG_CACHE.logs.lock().await;
+    let files = listed
+        .into_iter()
+        .map(|(path, size)| LogFile {
+            name: path
+                .strip_prefix(&logs_dir)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .into_owned(),
+            size,
+            date: log_file_date(&path),
+            cached: cache.contains_key(&path),
+        })
+        .collect::<Vec<_>>();
+    Ok(Json(files))
+}
+
+#[derive(Debug, Serialize)]
+struct ExportedLine {
+    player: String,
+    timestamp: String,
+    message: String,
+}
+
+/// Streams every attributed log line as newline delimited JSON.
+pub async fn export(
+    State(AppState { config, .. }): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    let lines = parse_logs(&config).await?.map(|line| {
+        let exported = ExportedLine {
+            // logs are written in the host's time
+            timestamp: match Local.from_local_datetime(&line.timestamp).earliest() {
+                Some(t) => t.to_rfc3339(),
+                None => line.timestamp.to_string(),
+            },
+            player: line.player,
+            message: line.message,
+        };
+        serde_json::to_string(&exported).map(|mut json| {
+            json.push('\n');
+            json
+        })
+    });
+    Ok((
+        [(CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(lines),
+    ))
+}
+
+/// The main parsing function
+pub async fn parse_logs(config: &Config) -> Result<impl Stream<Item = LogLine> + use<>, Error> {
+    Ok(parse_log_files(config)
+        .await?
+        .flat_map(|log| futures::stream::iter(log.lines)))
+}
+
+/// How often `latest.log` is checked for new lines by [`tail_latest_log`].
+const TAIL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The lines logged to `latest.log` from now on. The log is read from where it was left every
+/// time, from its start again once it's rotated, and a partial line waits for the rest of it.
+pub fn tail_latest_log(config: &Config) -> Result<impl Stream<Item = LogLine> + use<>, Error> {
+    Ok(tail_log_source(
+        Arc::new(LogsDir(config.server_dir.join("logs"))),
+        players(config)?,
+        config.noise_patterns.clone().unwrap_or_default(),
+    ))
+}
+
+/// The lines logged to the latest log of `source` from now on, see [`tail_latest_log`].
+pub fn tail_log_source(
+    source: Arc<dyn LogSource>,
+    whitelist: Vec<WhitelistEntry>,
+    noise: Vec<Regex>,
+) -> impl Stream<Item = LogLine> {
+    let offset = source
+        .open(&source.latest())
+        .and_then(|mut log| log.seek(SeekFrom::End(0)))
+        .unwrap_or(0);
+    let ticker = tokio::time::interval(TAIL_INTERVAL);
+    let state = (ticker, offset, source, whitelist, noise);
+    futures::stream::unfold(
+        state,
+        |(mut ticker, mut offset, source, whitelist, noise)| async move {
+            ticker.tick().await;
+            let read = {
+                let (source, mut offset) = (source.clone(), offset);
+                tokio::task::spawn_blocking(move || {
+                    read_appended(&*source, &mut offset).map(|appended| (appended, offset))
+                })
+                .await
+                .unwrap()
+            };
+            let lines = match read {
+                Ok((appended, read_up_to)) => {
+                    offset = read_up_to;
+                    parse_log(&appended, &whitelist, &noise).lines
+                }
+                Err(e) => {
+                    tracing::warn!(error = ?e, path = ?source.latest(), "failed to tail log");
+                    vec![]
+                }
+            };
+            Some((
+                futures::stream::iter(lines),
+                (ticker, offset, source, whitelist, noise),
+            ))
+        },
+    )
+    .flatten()
+}
+
+/// Reads the complete lines written to the latest log of `source` after `offset`, moving it past
+/// them.
+fn read_appended(source: &dyn LogSource, offset: &mut u64) -> io::Result<String> {
+    let mut log = match source.open(&source.latest()) {
+        Ok(log) => log,
+        // between the rotation and the new log being created
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(String::new()),
+        Err(e) => return Err(e),
+    };
+    let len = log.seek(SeekFrom::End(0))?;
+    if len < *offset {
+        // rotated or truncated
+        *offset = 0;
+    }
+    log.seek(SeekFrom::Start(*offset))?;
+    let mut appended = Vec::new();
+    log.take(len - *offset).read_to_end(&mut appended)?;
+    let complete = appended
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |newline| newline + 1);
+    appended.truncate(complete);
+    *offset += complete as u64;
+    Ok(String::from_utf8_lossy(&appended).into_owned())
+}
+
+/// The day before which rotated logs are skipped, when `max_log_age_days` is set.
+fn log_cutoff(config: &Config) -> Option<NaiveDate> {
+    config
+        .max_log_age_days
+        .and_then(|days| Local::now().date_naive().checked_sub_days(Days::new(days)))
+}
+
+/// The rotated logs that are parsed. The newest is a copy of `latest.log` while it's there, then
+/// logs from before `cutoff` and debug logs unless `include_debug_logs` is set are skipped.
+fn parsed_rotated_logs(
+    mut files: Vec<PathBuf>,
+    has_latest: bool,
+    cutoff: Option<NaiveDate>,
+    include_debug_logs: bool,
+) -> Vec<PathBuf> {
+    if has_latest {
+        files.pop();
+    }
+    if let Some(cutoff) = cutoff {
+        files.retain(|f| log_file_date(f).is_none_or(|date| date >= cutoff));
+    }
+    if !include_debug_logs {
+        files.retain(|f| !is_debug_log(f));
+    }
+    files
+}
+
+/// Parses every log file, oldest first, ending with `latest.log`.
+pub async fn parse_log_files(
+    config: &Config,
+) -> Result<impl Stream<Item = ParsedLog> + use<>, Error> {
+    let whitelist = players(config)?;
+    let cutoff = log_cutoff(config);
+    let noise = config.noise_patterns.clone().unwrap_or_default();
+    let logs_dir = config.server_dir.join("logs");
+    parse_log_source(
+        Arc::new(LogsDir(logs_dir)),
+        Arc::new(whitelist),
+        Arc::new(noise),
+        cutoff,
+        config.include_debug_logs,
+    )
+}
+
+/// The players whose lines are parsed: the whitelist, plus the operators and the usercache when
+/// enabled, minus the ignored players. The old names in `player_aliases` become aliases of the
+/// canonical player, so their lines are attributed to them.
+pub fn players(config: &Config) -> Result<Vec<WhitelistEntry>, Error> {
+    let whitelist_path = config.server_dir.join("whitelist.json");
+    tracing::debug!(?whitelist_path, "opening whitelist");
+    let mut whitelist: Vec<WhitelistEntry> = read_player_list(&whitelist_path)?;
+    if config.use_ops {
+        let ops_path = config.server_dir.join("ops.json");
+        tracing::debug!(?ops_path, "opening ops");
+        let ops: Vec<WhitelistEntry> = read_player_list(&ops_path)?;
+        for op in ops {
+            if !whitelist.iter().any(|e| e.name == op.name) {
+                whitelist.push(op);
+            }
+        }
+    }
+    if config.use_usercache {
+        let usercache_path = config.server_dir.join("usercache.json");
+        tracing::debug!(?usercache_path, "opening usercache");
+        merge_usercache(&mut whitelist, read_player_list(&usercache_path)?);
+    }
+    if let Some(aliases) = &config.player_aliases {
+        for (old, canonical) in aliases {
+            let Some(entry) = whitelist.iter_mut().find(|e| e.name == *canonical) else {
+                continue;
+            };
+            if !entry.aliases.contains(old) {
+                entry.aliases.push(old.clone());
+            }
+            whitelist.retain(|e| e.name != *old);
+        }
+    }
+    if let Some(ignored) = &config.ignored_players {
+        whitelist.retain(|e| !ignored.contains(&e.name));
+    }
+    Ok(whitelist)
+}
+
+/// Parses a single file of the logs directory, bypassing the cache and the age and debug
+/// filters. `name` is relative to the logs directory, like the names `/logs/files` lists, and
+/// can be in a subdirectory but not outside of it.
+pub async fn parse_log_file(config: &Config, name: &str) -> Result<ParsedLog, Error> {
+    let canonicalize = async |path: PathBuf| match tokio::fs::canonicalize(path).await {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err(Error::NotFound),
+        canonical => Ok(canonical?),
+    };
+    let logs_dir = LogsDir(canonicalize(config.server_dir.join("logs")).await?);
+    let path = canonicalize(logs_dir.0.join(name)).await?;
+    if !path.starts_with(&logs_dir.0) || !path.is_file() {
+        return Err(Error::BadRequest(format!("{name} isn't a log file")));
+    }
+    let whitelist = players(config)?;
+    let noise = config.noise_patterns.clone().unwrap_or_default();
+    let file = match logs_dir.open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(Error::NotFound),
+        Err(e) => return Err(e.into()),
+    };
+    tokio::task::spawn_blocking(move || {
+        let mut contents = Vec::new();
+        read_log(file, &mut contents)?;
+        Ok(parse_log(
+            &String::from_utf8_lossy(&contents),
+            &whitelist,
+            &noise,
+        ))
+    })
+    .await
+    .unwrap()
+}
+
+/// Parses the logs of `source`, skipping rotated logs from before `cutoff`, debug logs unless
+/// `include_debug_logs` is set and lines matching any of the `noise` patterns.
+pub fn parse_log_source(
+    source: Arc<dyn LogSource>,
+    whitelist: Arc<Vec<WhitelistEntry>>,
+    noise: Arc<Vec<Regex>>,
+    cutoff: Option<NaiveDate>,
+    include_debug_logs: bool,
+) -> Result<impl Stream<Item = ParsedLog>, Error> {
+    tracing::debug!("listing logs");
+    let files = source.rotated()?;
+    let latest_log_path = source.latest();
+    tracing::debug!(?latest_log_path, "reading log");
+    let mut contents = Vec::new();
+    let (latest, has_latest) = match source
+        .open(&latest_log_path)
+        .and_then(|f| read_log(f, &mut contents))
+    {
+        Ok(_) => (
+            parse_log(&String::from_utf8_lossy(&contents), &whitelist, &noise),
+            true,
+        ),
+        // the server is stopped and its last log was rotated, so the newest rotated log is the
+        // only copy of it
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            tracing::warn!(?latest_log_path, "latest log is missing");
+            (ParsedLog::default(), false)
+        }
+        Err(e) => {
+            tracing::error!(error = ?e, "failed to read lattest log");
+            (ParsedLog::default(), true)
+        }
+    };
+    let files = parsed_rotated_logs(files, has_latest, cutoff, include_debug_logs);
     let death_record_futures = {
+        let source = source.clone();
         let whitelist = whitelist.clone();
+        let noise = noise.clone();
         futures::stream::iter(files)
-            .filter(|p| std::future::ready(!p.to_string_lossy().contains("debug")))
             .map(move |file_path| {
                 let whitelist = whitelist.clone();
+                let noise = noise.clone();
+                let source = source.clone();
                 async move {
-                    if let Some(cached) = LOG_CACHE.lock().await.get(&file_path) {
-                        return cached.clone().into_iter();
+                    if let Some(cached) = source.cache().logs.lock().await.get(&file_path) {
+                        source.cache().counters.hit();
+                        return cached.clone();
                     };
+                    source.cache().counters.miss();
 
                     let whitelist = whitelist.clone();
-                    let read_result = tokio::task::spawn_blocking(move || {
-                        tracing::error_span!("LOG PARSING", ?file_path).in_scope(|| {
-                            let file = match File::open(&file_path) {
+                    let reader = source.clone();
+                    // a timed out request can't cancel this, but the log is still cached for the
+                    // next one so the work isn't lost
+                    let read_result =
+                        tokio::task::spawn_blocking(move || {
+                            tracing::error_span!("LOG PARSING", ?file_path).in_scope(|| {
+                            let file = match reader.open(&file_path) {
                                 Ok(f) => f,
                                 Err(e) => {
                                     tracing::error!(?file_path, error = ?e, "failed to read log");
                                     return None;
                                 }
                             };
-                            let mut gz = GzDecoder::new(std::io::BufReader::new(file));
-                            let mut contents = String::new();
+                            let mut contents = Vec::new();
 
-                            // Decompress and read to string (handles UTF-8)
-                            match gz.read_to_string(&mut contents) {
-                                Ok(_) => Some((file_path, parse_log(&contents, &whitelist))),
+                            // On error `read_to_end` keeps whatever was decompressed before it,
+                            // which is the case for logs that are still being rotated.
+                            let complete = match read_log(file, &mut contents) {
+                                Ok(_) => true,
                                 Err(e) => {
-                                    tracing::error!(error = ?e, "failed to parse log");
-                                    None
+                                    tracing::warn!(
+                                        error = ?e,
+                                        bytes_read = contents.len(),
+                                        "log was only partially decompressed"
+                                    );
+                                    // the last line was probably cut short
+                                    let end = contents
+                                        .iter()
+                                        .rposition(|&b| b == b'\n')
+                                        .map_or(0, |i| i + 1);
+                                    contents.truncate(end);
+                                    false
                                 }
-                            }
+                            };
+                            let contents = String::from_utf8_lossy(&contents);
+                            Some((file_path, parse_log(&contents, &whitelist, &noise), complete))
+                        })
                         })
-                    })
-                    .await
-                    .unwrap();
-                    if let Some((file_path, records)) = read_result {
-                        LOG_CACHE.lock().await.insert(file_path, records.clone());
-                        records.into_iter()
+                        .await
+                        .unwrap();
+                    if let Some((file_path, records, complete)) = read_result {
+                        // partial logs are retried on the next request
+                        if complete {
+                            source
+                                .cache()
+                                .logs
+                                .lock()
+                                .await
+                                .insert(file_path, records.clone());
+                        }
+                        records
                     } else {
-                        vec![].into_iter()
+                        ParsedLog::default()
                     }
                 }
             })
             .buffered(usize::MAX)
     };
 
-    Ok(death_record_futures
-        .chain(futures::stream::iter([{
-            let latest_log_path = logs_dir.join("latest.log");
-            tracing::debug!(?latest_log_path, "reading log");
-            match std::fs::read_to_string(latest_log_path) {
-                Ok(contents) => parse_log(&contents, &whitelist).into_iter(),
-                Err(e) => {
-                    tracing::error!(error = ?e, "failed to read lattest log");
-                    vec![].into_iter()
-                }
-            }
-        }]))
-        .flat_map(futures::stream::iter))
+    Ok(death_record_futures.chain(futures::stream::iter([latest])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, ServerDir, gzip, log_line};
+    use axum::http::StatusCode;
+
+    fn whitelist(names: &[&str]) -> Arc<Vec<WhitelistEntry>> {
+        Arc::new(
+            names
+                .iter()
+                .map(|name| WhitelistEntry {
+                    name: name.to_string(),
+                    uuid: None,
+                    aliases: vec![],
+                })
+                .collect(),
+        )
+    }
+
+    async fn parse(source: Arc<InMemoryLogs>, names: &[&str]) -> Vec<(String, String)> {
+        parse_log_source(source, whitelist(names), Default::default(), None, false)
+            .unwrap()
+            .flat_map(|log| futures::stream::iter(log.lines))
+            .map(|line| (line.player, line.message))
+            .collect()
+            .await
+    }
+
+    fn line(player: &str, message: &str) -> (String, String) {
+        (player.to_owned(), message.to_owned())
+    }
+
+    #[test]
+    fn old_names_are_aliases_of_the_canonical_player() {
+        let server = ServerDir::new();
+        server.whitelist(&["alice", "ally", "bob"]);
+        let config = server.config(serde_json::json!({ "player_aliases": { "ally": "alice" } }));
+        let players = players(&config).unwrap();
+        let players = players
+            .iter()
+            .map(|p| (p.name.as_str(), p.aliases.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            players,
+            [("alice", vec!["ally".to_owned()]), ("bob", vec![])]
+        );
+    }
+
+    #[tokio::test]
+    async fn parses_in_memory_logs_oldest_first() {
+        let latest = log_line("07Jun2025 12:00:00.000", "bob drowned");
+        let source = Arc::new(InMemoryLogs::new([
+            ("latest.log", latest.clone().into_bytes()),
+            // rotated copy of latest.log, which isn't parsed twice
+            ("2025-06-07-1.log.gz", gzip(&latest)),
+            (
+                "2025-06-05-1.log.gz",
+                gzip(log_line("05Jun2025 10:00:00.000", "alice fell")),
+            ),
+            (
+                "2025-06-06-1.log",
+                log_line("06Jun2025 10:00:00.000", "carol hit the ground too hard").into_bytes(),
+            ),
+        ]));
+        assert_eq!(
+            parse(source, &["alice", "bob"]).await,
+            [line("alice", "fell"), line("bob", "drowned")]
+        );
+    }
+
+    #[tokio::test]
+    async fn sources_have_their_own_cache() {
+        let rotated = |message: &str| gzip(log_line("05Jun2025 10:00:00.000", message));
+        let source = |message| {
+            Arc::new(InMemoryLogs::new([
+                ("2025-06-05-1.log.gz", rotated(message)),
+                ("2025-06-06-1.log.gz", vec![]),
+                ("latest.log", vec![]),
+            ]))
+        };
+        let (first, second) = (source("alice fell"), source("alice drowned"));
+        assert_eq!(
+            parse(first.clone(), &["alice"]).await,
+            [line("alice", "fell")]
+        );
+        assert_eq!(
+            parse(second.clone(), &["alice"]).await,
+            [line("alice", "drowned")]
+        );
+        assert_eq!(
+            parse(first.clone(), &["alice"]).await,
+            [line("alice", "fell")]
+        );
+        let stats = first.cache().counters.stats(0);
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+        assert_eq!(second.cache().counters.stats(0).hits, 0);
+    }
+
+    #[tokio::test]
+    async fn tails_only_new_lines() {
+        let source = Arc::new(InMemoryLogs::new([(
+            "latest.log",
+            log_line("07Jun2025 12:00:00.000", "alice fell").into_bytes(),
+        )]));
+        let tail = tail_log_source(source.clone(), whitelist(&["alice"]).to_vec(), vec![]);
+        let mut tail = std::pin::pin!(tail);
+        source.append(
+            "latest.log",
+            log_line("07Jun2025 12:01:00.000", "alice drowned").as_bytes(),
+        );
+        let line = tokio::time::timeout(Duration::from_secs(5), tail.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(line.message, "drowned");
+    }
+
+    #[test]
+    fn partial_lines_wait_for_the_rest() {
+        let source = InMemoryLogs::new([("latest.log", b"first\nsec".to_vec())]);
+        let mut offset = 0;
+        assert_eq!(read_appended(&source, &mut offset).unwrap(), "first\n");
+        assert_eq!(offset, 6);
+        source.append("latest.log", b"ond\n");
+        assert_eq!(read_appended(&source, &mut offset).unwrap(), "second\n");
+        // rotated
+        let source = InMemoryLogs::new([("latest.log", b"new\n".to_vec())]);
+        assert_eq!(read_appended(&source, &mut offset).unwrap(), "new\n");
+    }
+
+    #[test]
+    fn rotated_logs_are_found_in_nested_directories() {
+        let server = ServerDir::new();
+        server
+            .write("logs/latest.log", "")
+            .write_gz("logs/2025-06-07-1.log.gz", "")
+            .write_gz("logs/2025/06/2025-06-05-2.log.gz", "")
+            .write_gz("logs/2025/06/2025-06-05-1.log.gz", "")
+            .write("logs/2024/12/2024-12-31-1.log", "")
+            .write("logs/2025/notes.txt", "")
+            .write("logs/2025/06/usercache.json", "");
+        let logs_dir = server.path().join("logs");
+        let files = rotated_log_files(&logs_dir).unwrap();
+        let files = files
+            .iter()
+            .map(|f| f.strip_prefix(&logs_dir).unwrap().to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            files,
+            [
+                "2024/12/2024-12-31-1.log",
+                "2025/06/2025-06-05-1.log.gz",
+                "2025/06/2025-06-05-2.log.gz",
+                "2025-06-07-1.log.gz",
+            ]
+        );
+    }
+
+    #[test]
+    fn debug_logs_are_told_by_their_file_name() {
+        assert!(is_debug_log(Path::new("logs/debug.log")));
+        assert!(is_debug_log(Path::new("logs/debug-1.log.gz")));
+        assert!(!is_debug_log(Path::new("logs/debug/2025-06-05-1.log.gz")));
+        assert!(is_debug_log(Path::new("logs/debug/debug-2.log.gz")));
+        assert!(!is_debug_log(Path::new("logs/2025-06-05-1.log.gz")));
+    }
+
+    #[test]
+    fn operators_are_players_with_use_ops() {
+        let server = ServerDir::new();
+        server.whitelist(&["alice"]).write(
+            "ops.json",
+            serde_json::json!([
+                { "uuid": "uuid-alice", "name": "alice", "level": 4, "bypassesPlayerLimit": false },
+                { "uuid": "uuid-root", "name": "root", "level": 4, "bypassesPlayerLimit": false },
+            ])
+            .to_string(),
+        );
+        let names = |extra| {
+            players(&server.config(extra))
+                .unwrap()
+                .into_iter()
+                .map(|p| p.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(serde_json::json!({})), ["alice"]);
+        assert_eq!(
+            names(serde_json::json!({ "use_ops": true })),
+            ["alice", "root"]
+        );
+    }
+
+    #[test]
+    fn timestamps_in_each_format() {
+        let expected = "2025-06-06T15:42:05.682".parse::<NaiveDateTime>().unwrap();
+        for timestamp in [
+            "06Jun2025 15:42:05.682",
+            "2025-06-06T15:42:05.682",
+            "2025-06-06 15:42:05,682",
+            "06Jun2025 03:42:05.682 PM",
+        ] {
+            let mut format = None;
+            assert_eq!(
+                parse_timestamp(timestamp, &mut format),
+                Ok(expected),
+                "{timestamp}"
+            );
+            assert!(format.is_some());
+        }
+    }
+
+    #[test]
+    fn timestamp_format_sticks_to_the_file() {
+        let mut format = None;
+        parse_timestamp("06Jun2025 15:42:05.682", &mut format).unwrap();
+        assert_eq!(format, Some(TIMESTAMP_FORMATS[0]));
+        assert!(parse_timestamp("2025-06-06T15:42:05.682", &mut format).is_err());
+        assert!(parse_timestamp("07Jun2025 01:00:00.000", &mut format).is_ok());
+    }
+
+    #[test]
+    fn splits_lines_on_each_metadata_end() {
+        let line = "[06Jun2025 15:42:05.682] [Server thread/INFO] [minecraft/MinecraftServer]: alice drowned";
+        assert_eq!(
+            split_line(line),
+            Some((
+                "[06Jun2025 15:42:05.682] [Server thread/INFO] [minecraft/MinecraftServer]",
+                "alice drowned"
+            ))
+        );
+        assert_eq!(
+            split_line("[15:42:05] [Server thread/INFO]:alice drowned"),
+            Some(("[15:42:05] [Server thread/INFO]", "alice drowned"))
+        );
+        assert_eq!(
+            split_line("[2025-06-06 15:42:05] [INFO] alice drowned"),
+            Some(("[2025-06-06 15:42:05] [INFO]", "alice drowned"))
+        );
+        // brackets in the message aren't metadata
+        assert_eq!(
+            split_line("[2025-06-06 15:42:05] alice was slain by [Intentional Game Design]"),
+            Some((
+                "[2025-06-06 15:42:05]",
+                "alice was slain by [Intentional Game Design]"
+            ))
+        );
+        assert_eq!(split_line("alice drowned"), None);
+    }
+
+    #[tokio::test]
+    async fn lists_the_logs_that_are_parsed() {
+        let server = ServerDir::new();
+        let latest = log_line("07Jun2025 12:00:00.000", "alice drowned");
+        server
+            .whitelist(&["alice"])
+            .write_gz("logs/2025-06-05-1.log.gz", "")
+            .write_gz("logs/sub/2025-06-06-1.log.gz", "")
+            .write_gz("logs/debug-1.log.gz", "")
+            .write_gz("logs/2025-06-07-1.log.gz", &latest)
+            .write("logs/latest.log", &latest);
+        let names = async |extra| {
+            let (_, body) = server.get(server.config(extra), "/logs/files").await;
+            body.as_array()
+                .unwrap()
+                .iter()
+                .map(|f| f["name"].as_str().unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(serde_json::json!({})).await,
+            [
+                "latest.log",
+                "sub/2025-06-06-1.log.gz",
+                "2025-06-05-1.log.gz"
+            ]
+        );
+        assert_eq!(
+            names(serde_json::json!({ "max_log_age_days": 30 })).await,
+            ["latest.log"]
+        );
+    }
+
+    #[tokio::test]
+    async fn debugs_nested_logs_but_nothing_outside_the_logs() {
+        let server = ServerDir::new();
+        server
+            .whitelist(&["alice"])
+            .write_gz(
+                "logs/sub/2025-06-06-1.log.gz",
+                log_line("06Jun2025 12:00:00.000", "alice drowned"),
+            )
+            .write("secret.txt", "");
+        let shared = testing::state(server.config(serde_json::json!({})));
+        let (status, body) =
+            testing::get(shared.clone(), "/deaths/debug?file=sub/2025-06-06-1.log.gz").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[0]["cause"], "drowned");
+        for (file, expected) in [
+            ("../secret.txt", StatusCode::BAD_REQUEST),
+            ("sub", StatusCode::BAD_REQUEST),
+            ("", StatusCode::BAD_REQUEST),
+            ("missing.log", StatusCode::NOT_FOUND),
+        ] {
+            let uri = format!("/deaths/debug?file={file}");
+            assert_eq!(
+                testing::get(shared.clone(), &uri).await.0,
+                expected,
+                "{file}"
+            );
+        }
+    }
 }
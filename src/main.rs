@@ -1,28 +1,616 @@
+mod advancements;
 mod deaths;
+mod filters;
 mod logs;
 mod mods;
+mod playtime;
+mod status;
+#[cfg(test)]
+mod testing;
 
 use askama::Template;
 use axum::{
     Router,
-    http::StatusCode,
-    response::{Html, IntoResponse, Redirect},
-    routing::get,
+    extract::{FromRef, OriginalUri, Request, State},
+    http::{
+        HeaderMap, HeaderValue, Method, StatusCode,
+        header::{
+            ACCEPT, AUTHORIZATION, CACHE_CONTROL, ETAG, IF_NONE_MATCH, RETRY_AFTER, VARY,
+            WWW_AUTHENTICATE,
+        },
+    },
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Json, Redirect, Response},
+    routing::{MethodRouter, get, post},
 };
-use serde::Deserialize;
+use base64::{Engine as _, prelude::BASE64_STANDARD};
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::json;
 use std::{
+    collections::HashMap,
     io,
+    net::SocketAddr,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use subtle::ConstantTimeEq as _;
+use tower_http::{
+    cors::{AllowOrigin, CorsLayer},
+    limit::RequestBodyLimitLayer,
+    services::{ServeDir, ServeFile},
+    timeout::TimeoutLayer,
 };
-use tower_http::services::{ServeDir, ServeFile};
 use tracing::level_filters::LevelFilter;
 use tracing_subscriber::{EnvFilter, fmt, layer::SubscriberExt as _, util::SubscriberInitExt as _};
+use utoipa::OpenApi;
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize)]
 struct Config {
     backups_dir: PathBuf,
     server_dir: PathBuf,
+    cache_max_age_secs: Option<u64>,
+    /// Timezone to display death timestamps in. When unset, timestamps are shown in the host's
+    /// local time, as they appear in the logs.
+    display_timezone: Option<chrono_tz::Tz>,
+    /// Rotated logs older than this many days are not parsed at all.
+    max_log_age_days: Option<u64>,
+    /// Cross reference `usercache.json` to attribute deaths to renamed players and players that
+    /// aren't whitelisted.
+    #[serde(default)]
+    use_usercache: bool,
+    /// Count operators from `ops.json` as players, for servers whose operators aren't
+    /// whitelisted.
+    #[serde(default)]
+    use_ops: bool,
+    modrinth_base_url: Option<String>,
+    /// Sub-path the site is hosted under when behind a reverse proxy, e.g. `/mc`.
+    base_path: Option<String>,
+    /// Whether to serve the web-exported maps. When unset, each map is served only if its
+    /// export directory exists.
+    map_enabled: Option<bool>,
+    /// Patterns that death messages must match, for servers whose death messages aren't vanilla
+    /// English. When a pattern has a `cause` capture group it's used as the cause, otherwise the
+    /// whole match is.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_regexes",
+        serialize_with = "serialize_regexes"
+    )]
+    death_messages: Option<Vec<Regex>>,
+    /// Log lines whose message matches any of these patterns are dropped before they're
+    /// attributed to a player, to silence mods that log lines starting with a player's name.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_regexes",
+        serialize_with = "serialize_regexes"
+    )]
+    noise_patterns: Option<Vec<Regex>>,
+    /// How to pick which file of a mod version goes in the modpack.
+    file_preference: Option<Vec<mods::FilePreference>>,
+    /// Directory with assets shared by the pages, served at `/static`. The favicon is taken from
+    /// here when present.
+    static_dir: Option<PathBuf>,
+    /// When set, the deaths page is re-aggregated in the background this often instead of when
+    /// it's requested.
+    refresh_interval_secs: Option<u64>,
+    /// When set, `/deaths` answers from the last aggregation and once it's older than this
+    /// re-aggregates in the background, so requests never wait on parsing after the first.
+    deaths_stale_after_secs: Option<u64>,
+    /// Compression of the modpack's entries, `stored` by default for compatibility.
+    modpack_compression: Option<mods::Compression>,
+    /// Compression level, only used with `deflated` (0-9).
+    modpack_compression_level: Option<i64>,
+    /// Requests that take longer than this are answered with a 504.
+    request_timeout_secs: Option<u64>,
+    /// Requests with a bigger body are answered with a 413. 64 KiB by default, the admin routes
+    /// are the only ones that take a body and they don't need much of one.
+    max_body_bytes: Option<usize>,
+    /// Whitelisted accounts that aren't really players, like map renderers or backup bots.
+    /// Nothing they log is attributed to them.
+    ignored_players: Option<Vec<String>>,
+    /// How many modpacks each client can generate or validate per minute, each one costs a
+    /// request to modrinth per mod.
+    modpack_rate_limit: Option<u32>,
+    /// Modrinth project ids of mods, by slug. Mods with an id are looked up by it so they keep
+    /// resolving if their project is renamed.
+    mod_project_ids: Option<HashMap<String, String>>,
+    /// Causes of genuine deaths that are left out of the stats, like `/kill`s while testing.
+    ignored_causes: Option<Vec<String>>,
+    /// The least stable mod versions that can go in the modpack, `release` by default.
+    min_version_type: Option<mods::VersionType>,
+    /// Where generated files, like modpack snapshots, are kept. `./cache` by default.
+    cache_dir: Option<PathBuf>,
+    /// User agent sent to modrinth, which asks for one that identifies the project and how to
+    /// contact its owner.
+    modrinth_user_agent: Option<String>,
+    /// How long a single request to modrinth can take before it's given up on, 15 by default.
+    modrinth_timeout_secs: Option<u64>,
+    /// Canonical names of players who changed name, by their old name. Deaths under an old name
+    /// are counted as the canonical player's.
+    player_aliases: Option<HashMap<String, String>>,
+    /// How numbers are written on the pages, `plain` by default.
+    #[serde(default)]
+    locale: filters::Locale,
+    /// Address of the minecraft server, as `host` or `host:port`, to show whether it's up.
+    server_address: Option<String>,
+    /// Directory with jinja templates that replace the built in ones for the home, deaths and
+    /// mods pages, at the same paths as in `templates/`. Pages without one keep the built in one.
+    template_dir: Option<PathBuf>,
+    /// Players with fewer deaths than this aren't listed on `/deaths`, though their deaths are
+    /// still counted.
+    min_deaths: Option<u64>,
+    /// Most players in a json response of `/deaths`, the rest are left out.
+    max_players_in_response: Option<usize>,
+    /// Most points in each chart of a json response of `/deaths`, the most common causes and
+    /// most recent dates are kept.
+    max_chart_points: Option<usize>,
+    /// Whether logs with `debug` in their name are parsed too.
+    #[serde(default)]
+    include_debug_logs: bool,
+    /// Origins of other sites allowed to call the routes from a browser, e.g.
+    /// `https://dashboard.example.com`. None by default.
+    allowed_origins: Option<Vec<String>>,
+    /// Loaders whose mods run on the server, most preferred first, e.g. `["neoforge", "forge"]`.
+    /// Only `neoforge` by default.
+    loaders: Option<Vec<String>>,
+    /// Labels shown on the charts instead of some causes, e.g. `"was pricked to death" =
+    /// "cactus"`. The other routes keep the causes as they were logged.
+    cause_rename: Option<HashMap<String, String>>,
+    /// strftime format of the days on the deaths over time charts, e.g. `%d.%m.%Y`. It has to
+    /// tell days apart since it's also what the deaths of each day are counted under.
+    /// `%d %b %Y` by default.
+    date_label_format: Option<String>,
+    /// Username and password for the admin and debug routes, which are open to anyone when
+    /// unset.
+    admin_credentials: Option<Credentials>,
+    /// Slugs of mods in the server's `mods/` that aren't advertised, like server only
+    /// anti-cheats. They're left out of `/mods` and the modpack.
+    mod_blocklist: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Credentials {
+    username: String,
+    #[serde(serialize_with = "redact")]
+    password: String,
+}
+
+/// Keeps secrets out of the printed config.
+fn redact<S: Serializer>(_: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str("<redacted>")
+}
+
+impl Credentials {
+    /// Whether the request's basic auth matches, compared in constant time so the credentials
+    /// can't be guessed from how long a rejection takes.
+    fn authorize(&self, headers: &HeaderMap) -> bool {
+        let Some(given) = headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split_once(' '))
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("basic"))
+            .and_then(|(_, encoded)| BASE64_STANDARD.decode(encoded.trim()).ok())
+        else {
+            return false;
+        };
+        let expected = format!("{}:{}", self.username, self.password);
+        expected.as_bytes().ct_eq(&given).into()
+    }
+}
+
+fn deserialize_regexes<'de, D>(deserializer: D) -> Result<Option<Vec<Regex>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<Vec<String>>::deserialize(deserializer)?
+        .map(|patterns| {
+            patterns
+                .iter()
+                .map(|p| Regex::new(p).map_err(serde::de::Error::custom))
+                .collect()
+        })
+        .transpose()
+}
+
+fn serialize_regexes<S: Serializer>(
+    regexes: &Option<Vec<Regex>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    regexes
+        .as_ref()
+        .map(|regexes| regexes.iter().map(Regex::as_str).collect::<Vec<_>>())
+        .serialize(serializer)
+}
+
+impl Config {
+    const DEFAULT_CACHE_MAX_AGE_SECS: u64 = 60;
+    const DEFAULT_MODRINTH_BASE_URL: &str = "https://api.modrinth.com/v2";
+    const DEFAULT_MODRINTH_TIMEOUT: Duration = Duration::from_secs(15);
+
+    fn validate(mut self) -> Result<Self, config::ConfigError> {
+        if let Some(base_path) = &mut self.base_path {
+            let trimmed = base_path.trim_matches('/');
+            *base_path = if trimmed.is_empty() {
+                String::new()
+            } else {
+                format!("/{trimmed}")
+            };
+        }
+        if let Err(e) = reqwest::Url::parse(self.modrinth_base_url()) {
+            return Err(config::ConfigError::Message(format!(
+                "invalid modrinth_base_url: {e}"
+            )));
+        }
+        if self.modrinth_timeout_secs == Some(0) {
+            return Err(config::ConfigError::Message(
+                "modrinth_timeout_secs must be at least 1".to_owned(),
+            ));
+        }
+        if self.modpack_rate_limit == Some(0) {
+            return Err(config::ConfigError::Message(
+                "modpack_rate_limit must be at least 1".to_owned(),
+            ));
+        }
+        if let Some(level) = self.modpack_compression_level
+            && !(0..=9).contains(&level)
+        {
+            return Err(config::ConfigError::Message(format!(
+                "invalid modpack_compression_level {level}, expected 0-9"
+            )));
+        }
+        if self.loaders.as_ref().is_some_and(Vec::is_empty) {
+            return Err(config::ConfigError::Message(
+                "loaders must have at least one loader".to_owned(),
+            ));
+        }
+        for origin in self.allowed_origins.iter().flatten() {
+            let valid = reqwest::Url::parse(origin).is_ok_and(|url| {
+                matches!(url.scheme(), "http" | "https")
+                    && url.origin().ascii_serialization() == *origin
+            });
+            if !valid {
+                return Err(config::ConfigError::Message(format!(
+                    "invalid allowed_origins entry {origin}, expected e.g. https://example.com"
+                )));
+            }
+        }
+        if let Some(format) = &self.date_label_format {
+            let items = chrono::format::StrftimeItems::new(format)
+                .parse()
+                .map_err(|e| {
+                    config::ConfigError::Message(format!("invalid date_label_format {format}: {e}"))
+                })?;
+            // two years, so every day of the month and of the week and a leap day are covered
+            let mut labels = HashMap::new();
+            let start = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+            for day in start.iter_days().take(731) {
+                let label = day.format_with_items(items.iter()).to_string();
+                if let Some(other) = labels.insert(label.clone(), day) {
+                    return Err(config::ConfigError::Message(format!(
+                        "date_label_format {format} labels both {other} and {day} as {label}, \
+                         it has to tell days apart"
+                    )));
+                }
+            }
+        }
+        if let Some(address) = &self.server_address
+            && status::parse_address(address).is_none()
+        {
+            return Err(config::ConfigError::Message(format!(
+                "invalid server_address {address}, expected host or host:port"
+            )));
+        }
+        Ok(self)
+    }
+
+    /// The config as it's used, with the defaults of the settings that have one filled in.
+    fn resolved(&self) -> serde_json::Result<serde_json::Value> {
+        let mut resolved = serde_json::to_value(self)?;
+        let defaults = [
+            ("base_path", json!(self.base_path())),
+            (
+                "cache_max_age_secs",
+                json!(
+                    self.cache_max_age_secs
+                        .unwrap_or(Self::DEFAULT_CACHE_MAX_AGE_SECS)
+                ),
+            ),
+            ("modrinth_base_url", json!(self.modrinth_base_url())),
+            ("modrinth_user_agent", json!(self.modrinth_user_agent())),
+            (
+                "modrinth_timeout_secs",
+                json!(self.modrinth_timeout().as_secs()),
+            ),
+            ("file_preference", json!(self.file_preference())),
+            (
+                "modpack_compression",
+                json!(self.modpack_compression.unwrap_or_default()),
+            ),
+            ("min_version_type", json!(self.min_version_type())),
+            ("cache_dir", json!(self.cache_dir())),
+            ("loaders", json!(self.loaders())),
+            ("date_label_format", json!(self.date_label_format())),
+            ("max_body_bytes", json!(self.max_body_bytes())),
+        ];
+        for (key, value) in defaults {
+            resolved[key] = value;
+        }
+        Ok(resolved)
+    }
+
+    fn modrinth_user_agent(&self) -> String {
+        self.modrinth_user_agent
+            .clone()
+            .unwrap_or_else(|| format!("mendess/mc-frontend/{}", env!("CARGO_PKG_VERSION")))
+    }
+
+    /// The normalized base path, either empty or starting with a `/` and without a trailing one.
+    fn base_path(&self) -> &str {
+        self.base_path.as_deref().unwrap_or_default()
+    }
+
+    fn max_body_bytes(&self) -> usize {
+        self.max_body_bytes.unwrap_or(64 * 1024)
+    }
+
+    fn date_label_format(&self) -> &str {
+        self.date_label_format.as_deref().unwrap_or("%d %b %Y")
+    }
+
+    fn favicon(&self) -> PathBuf {
+        self.static_dir
+            .as_ref()
+            .map(|dir| dir.join("favicon.ico"))
+            .filter(|favicon| favicon.is_file())
+            .unwrap_or_else(|| PathBuf::from("./assets/favicon.ico"))
+    }
+
+    fn file_preference(&self) -> &[mods::FilePreference] {
+        self.file_preference
+            .as_deref()
+            .unwrap_or(mods::FilePreference::DEFAULT)
+    }
+
+    /// Where the index of the modpack generated with the given version is saved.
+    fn cache_dir(&self) -> &Path {
+        self.cache_dir.as_deref().unwrap_or("./cache".as_ref())
+    }
+
+    fn snapshot_path(&self, version_id: &str) -> PathBuf {
+        self.cache_dir()
+            .join("modpacks")
+            .join(format!("{version_id}.json"))
+    }
+
+    /// Where the modpack with the jars in it is kept, by the etag of the modpack.
+    fn embedded_pack_path(&self, etag: &str) -> PathBuf {
+        self.cache_dir()
+            .join("embedded")
+            .join(format!("{}.mrpack", etag.trim_matches('"')))
+    }
+
+    fn loaders(&self) -> Vec<&str> {
+        match &self.loaders {
+            Some(loaders) => loaders.iter().map(String::as_str).collect(),
+            None => vec![mods::LOADER],
+        }
+    }
+
+    fn min_version_type(&self) -> mods::VersionType {
+        self.min_version_type.unwrap_or(mods::VersionType::Release)
+    }
+
+    fn project_id(&self, slug: &str) -> Option<String> {
+        self.mod_project_ids.as_ref()?.get(slug).cloned()
+    }
+
+    fn mod_blocked(&self, slug: &str) -> bool {
+        self.mod_blocklist
+            .as_ref()
+            .is_some_and(|blocklist| blocklist.iter().any(|b| b == slug))
+    }
+
+    fn modrinth_timeout(&self) -> Duration {
+        self.modrinth_timeout_secs
+            .map_or(Self::DEFAULT_MODRINTH_TIMEOUT, Duration::from_secs)
+    }
+
+    fn modrinth_base_url(&self) -> &str {
+        self.modrinth_base_url
+            .as_deref()
+            .unwrap_or(Self::DEFAULT_MODRINTH_BASE_URL)
+            .trim_end_matches('/')
+    }
+
+    fn cache_headers(&self, etag: Option<&str>) -> HeaderMap {
+        let max_age = self
+            .cache_max_age_secs
+            .unwrap_or(Self::DEFAULT_CACHE_MAX_AGE_SECS);
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CACHE_CONTROL,
+            HeaderValue::from_str(&format!("max-age={max_age}")).unwrap(),
+        );
+        headers.insert(VARY, HeaderValue::from_static("accept"));
+        if let Some(etag) = etag.and_then(|e| HeaderValue::from_str(e).ok()) {
+            headers.insert(ETAG, etag);
+        }
+        headers
+    }
+}
+
+/// Everything shared by the handlers.
+#[derive(Clone)]
+struct AppState {
+    config: Arc<Config>,
+    /// Client for modrinth, shared so connections are reused.
+    http: reqwest::Client,
+    refreshed_deaths: deaths::Refreshed,
+}
+
+impl AppState {
+    fn new(config: Arc<Config>) -> reqwest::Result<Self> {
+        Ok(Self {
+            http: reqwest::Client::builder()
+                .user_agent(config.modrinth_user_agent())
+                .timeout(config.modrinth_timeout())
+                .build()?,
+            config,
+            refreshed_deaths: Default::default(),
+        })
+    }
+}
+
+/// The state the router is served with. `/admin/reload` swaps the [`AppState`] in it, and
+/// handlers extract whichever one is current when their request comes in.
+#[derive(Clone)]
+struct SharedState(Arc<RwLock<AppState>>);
+
+impl FromRef<SharedState> for AppState {
+    fn from_ref(shared: &SharedState) -> Self {
+        shared.0.read().unwrap().clone()
+    }
+}
+
+/// Reads the config again and starts serving with it, keeping the current one if it's invalid.
+/// Settings that shape the routes, like `base_path`, the maps, `static_dir`, `allowed_origins`,
+/// `request_timeout_secs` and `refresh_interval_secs`, only change on a restart.
+async fn reload(State(shared): State<SharedState>) -> Result<impl IntoResponse, Error> {
+    swap_config(&shared, get_configuration()?).await?;
+    tracing::info!("reloaded config");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Starts serving with `config`. What was cached with the old one is forgotten: the aggregated
+/// deaths go with the old state, the parsed logs and resolved mods are cleared.
+async fn swap_config(shared: &SharedState, config: Config) -> reqwest::Result<()> {
+    let state = AppState::new(Arc::new(config))?;
+    *shared.0.write().unwrap() = state;
+    // after the swap, so requests still served with the old config can't fill them again
+    logs::clear_cache().await;
+    mods::clear_caches();
+    Ok(())
+}
+
+/// Whether the client prefers JSON over HTML according to its `Accept` header. Clients that
+/// don't say, or accept anything, get HTML.
+fn wants_json(headers: &HeaderMap) -> bool {
+    let quality = |media_type: &str| {
+        headers
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .filter_map(|entry| {
+                let mut params = entry.split(';').map(str::trim);
+                (params.next()? == media_type).then(|| {
+                    params
+                        .find_map(|p| p.strip_prefix("q=")?.parse::<f32>().ok())
+                        .unwrap_or(1.0)
+                })
+            })
+            .reduce(f32::max)
+    };
+    match (quality("application/json"), quality("text/html")) {
+        (Some(json), Some(html)) => json > html,
+        (Some(json), None) => json > 0.0,
+        _ => false,
+    }
+}
+
+/// Hit and miss counts of a cache.
+struct CacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CacheCounters {
+    const fn new() -> Self {
+        Self {
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    fn hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn stats(&self, entries: usize) -> CacheStats {
+        CacheStats {
+            entries,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct CacheStats {
+    entries: usize,
+    hits: u64,
+    misses: u64,
+}
+
+async fn cache_stats() -> Json<HashMap<&'static str, CacheStats>> {
+    Json(HashMap::from([
+        ("logs", logs::cache_stats().await),
+        ("mods", mods::cache_stats()),
+    ]))
+}
+
+/// A page rendered from a template.
+trait Page: Template + Serialize {
+    /// Path of a template in `template_dir` that replaces the compiled one, for pages that can be
+    /// customized.
+    const OVERRIDE: Option<&'static str> = None;
+}
+
+/// Renders a page with its template from `template_dir` if there is one, falling back to the
+/// compiled template.
+fn render<T: Page>(config: &Config, page: &T) -> Result<String, Error> {
+    let custom = T::OVERRIDE
+        .zip(config.template_dir.as_deref())
+        .map(|(name, dir)| dir.join(name));
+    let source = match custom.as_deref().map(std::fs::read_to_string) {
+        Some(Ok(source)) => source,
+        Some(Err(e)) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+        _ => return Ok(page.render()?),
+    };
+    let context = minijinja::context! {
+        base_path => config.base_path(),
+        ..minijinja::Value::from_serialize(page)
+    };
+    Ok(minijinja::Environment::new().render_str(&source, context)?)
+}
+
+/// Renders a page as HTML or JSON, depending on what the client asked for.
+fn negotiate<T: Page>(config: &Config, headers: &HeaderMap, page: T) -> Result<Response, Error> {
+    if wants_json(headers) {
+        Ok(Json(page).into_response())
+    } else {
+        Ok(Html(render(config, &page)?).into_response())
+    }
+}
+
+/// Whether the request's `If-None-Match` header matches the given etag.
+fn etag_matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag == etag)
 }
 
 fn get_configuration() -> Result<Config, config::ConfigError> {
@@ -30,22 +618,74 @@ fn get_configuration() -> Result<Config, config::ConfigError> {
         .add_source(config::File::with_name("config/conf").required(false))
         .build()
         .and_then(config::Config::try_deserialize)
+        .and_then(Config::validate)
 }
 
-fn add_map_routes(
-    mut router: Router<Arc<Config>>,
-    config: &Config,
-    maps: &[(&str, &str)],
-) -> Router<Arc<Config>> {
-    let base = Path::new("map/web-export");
-    for (map, dir) in maps {
+/// A web-exported map, along with the old urls that redirect to it.
+#[derive(Debug)]
+struct MapRoute {
+    /// Name shown in the maps page, unlisted maps are still served.
+    name: Option<&'static str>,
+    route: &'static str,
+    dir: &'static str,
+    aliases: &'static [&'static str],
+}
+
+const MAPS: &[MapRoute] = &[
+    MapRoute {
+        name: Some("Overworld"),
+        route: "/maps/overworld/",
+        dir: "overworld-day",
+        aliases: &["/super-secret-map"],
+    },
+    MapRoute {
+        name: Some("CN (Comboios do Nether)"),
+        route: "/maps/nether/",
+        dir: "nether",
+        aliases: &["/super-secret-map-nether"],
+    },
+    MapRoute {
+        name: None,
+        route: "/super-secret-map-nether-mid/",
+        dir: "nether-mid",
+        aliases: &[],
+    },
+];
+
+impl Config {
+    fn map_dir(&self, map: &MapRoute) -> PathBuf {
+        self.backups_dir.join("map/web-export").join(map.dir)
+    }
+
+    /// The maps that should be served, either because they were explicitly enabled or because
+    /// they have been exported.
+    fn enabled_maps(&self) -> impl Iterator<Item = &'static MapRoute> {
+        MAPS.iter().filter(|map| {
+            self.map_enabled
+                .unwrap_or_else(|| self.map_dir(map).is_dir())
+        })
+    }
+}
+
+fn add_map_routes(mut router: Router<SharedState>, config: &Config) -> Router<SharedState> {
+    for map in config.enabled_maps() {
+        tracing::info!(route = map.route, "registering map route");
+        let target = format!("{}{}", config.base_path(), map.route);
         router = router
-            .route(map.trim_end_matches("/"), get(Redirect::to(map)))
+            .route(map.route.trim_end_matches("/"), get(Redirect::to(&target)))
             .nest_service(
-                map,
-                ServeDir::new(config.backups_dir.join(base).join(dir))
-                    .append_index_html_on_directories(true),
+                map.route,
+                ServeDir::new(config.map_dir(map)).append_index_html_on_directories(true),
             );
+        for alias in map.aliases {
+            router = router
+                .route(alias, get(Redirect::to(&target)))
+                .route(&format!("{alias}/"), get(Redirect::to(&target)));
+        }
+    }
+    if config.enabled_maps().next().is_some() {
+        tracing::info!(route = "/maps", "registering maps page");
+        router = router.route("/maps", get(maps));
     }
     router
 }
@@ -61,42 +701,334 @@ fn init_tracing() {
         .init();
 }
 
+/// The OpenAPI document of the JSON endpoints.
+#[derive(OpenApi)]
+#[openapi(paths(
+    advancements::advancements,
+    deaths::deaths,
+    deaths::cause,
+    deaths::recent,
+    deaths::summary,
+    deaths::heatmap,
+    deaths::calendar,
+    deaths::pvp,
+    deaths::player,
+    deaths::compare,
+    deaths::efficiency,
+    logs::files,
+    mods::get_mods,
+    playtime::playtime,
+    status::status,
+))]
+struct ApiDoc;
+
+async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// A route that is always served.
+struct RouteEntry {
+    path: &'static str,
+    description: &'static str,
+    handler: fn() -> MethodRouter<SharedState>,
+}
+
+impl RouteEntry {
+    /// Whether the route is behind `admin_credentials`.
+    fn requires_auth(&self) -> bool {
+        self.path.starts_with("/admin/") || self.path == "/deaths/debug"
+    }
+}
+
+/// Rejects requests to the admin and debug routes without the `admin_credentials`.
+async fn require_admin(
+    State(AppState { config, .. }): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, Error> {
+    match &config.admin_credentials {
+        Some(credentials) if !credentials.authorize(request.headers()) => Err(Error::Unauthorized),
+        _ => Ok(next.run(request).await),
+    }
+}
+
+const ROUTES: &[RouteEntry] = &[
+    RouteEntry {
+        path: "/",
+        description: "Home page",
+        handler: || get(index),
+    },
+    RouteEntry {
+        path: "/routes",
+        description: "This list of routes",
+        handler: || get(routes),
+    },
+    RouteEntry {
+        path: "/advancements",
+        description: "Advancements of each player",
+        handler: || get(advancements::advancements),
+    },
+    RouteEntry {
+        path: "/deaths",
+        description: "Death statistics",
+        handler: || get(deaths::deaths),
+    },
+    RouteEntry {
+        path: "/deaths/cause",
+        description: "Deaths with a given cause",
+        handler: || get(deaths::cause),
+    },
+    RouteEntry {
+        path: "/deaths/summary",
+        description: "Deaths per year and their top cause",
+        handler: || get(deaths::summary),
+    },
+    RouteEntry {
+        path: "/deaths/recent",
+        description: "Deaths after a given time",
+        handler: || get(deaths::recent),
+    },
+    RouteEntry {
+        path: "/deaths/debug",
+        description: "Deaths found in a single log file, e.g. /deaths/debug?file=latest.log",
+        handler: || get(deaths::debug),
+    },
+    RouteEntry {
+        path: "/deaths/export.jsonl",
+        description: "Every death, as newline delimited JSON",
+        handler: || get(deaths::export),
+    },
+    RouteEntry {
+        path: "/deaths/player/{name}",
+        description: "A single player's stats, e.g. /deaths/player/alice?year=2025",
+        handler: || get(deaths::player),
+    },
+    RouteEntry {
+        path: "/deaths/compare",
+        description: "Two players head to head, e.g. /deaths/compare?a=alice&b=bob",
+        handler: || get(deaths::compare),
+    },
+    RouteEntry {
+        path: "/deaths/events",
+        description: "New deaths as server-sent events, as they're logged",
+        handler: || get(deaths::events),
+    },
+    RouteEntry {
+        path: "/deaths/efficiency",
+        description: "Players by deaths per hour played",
+        handler: || get(deaths::efficiency),
+    },
+    RouteEntry {
+        path: "/deaths/pvp",
+        description: "Kills and deaths between players, e.g. /deaths/pvp?year=2025",
+        handler: || get(deaths::pvp),
+    },
+    RouteEntry {
+        path: "/deaths/heatmap",
+        description: "Deaths by day of the week and hour",
+        handler: || get(deaths::heatmap),
+    },
+    RouteEntry {
+        path: "/deaths/calendar",
+        description: "Deaths on each day of a year, e.g. /deaths/calendar?year=2025&player=alice",
+        handler: || get(deaths::calendar),
+    },
+    RouteEntry {
+        path: "/playtime",
+        description: "Hours played by each player",
+        handler: || get(playtime::playtime),
+    },
+    RouteEntry {
+        path: "/logs/files",
+        description: "The server logs that are parsed",
+        handler: || get(logs::files),
+    },
+    RouteEntry {
+        path: "/logs/export.jsonl",
+        description: "Every player's log lines as newline delimited JSON",
+        handler: || get(logs::export),
+    },
+    RouteEntry {
+        path: "/admin/reload",
+        description: "Reads the config again without a restart, with a POST",
+        handler: || post(reload),
+    },
+    RouteEntry {
+        path: "/admin/cache/stats",
+        description: "Size, hits and misses of the log and mod caches",
+        handler: || get(cache_stats),
+    },
+    RouteEntry {
+        path: "/openapi.json",
+        description: "OpenAPI document of the JSON endpoints",
+        handler: || get(openapi),
+    },
+    RouteEntry {
+        path: "/status",
+        description: "Whether the server is online and who is on it",
+        handler: || get(status::status),
+    },
+    RouteEntry {
+        path: "/mods",
+        description: "Mod list",
+        handler: || get(mods::get_mods),
+    },
+    RouteEntry {
+        path: "/loader-version",
+        description: "NeoForge version of the server, as plain text",
+        handler: || get(mods::loader_version),
+    },
+    RouteEntry {
+        path: "/mods/validate",
+        description: "Whether every mod resolves on modrinth",
+        handler: || get(mods::validate_mods),
+    },
+    RouteEntry {
+        path: "/mods/large-biomes.mrpack",
+        description: "Modpack download",
+        handler: || get(mods::generate_mod_pack),
+    },
+    RouteEntry {
+        path: "/modpack/index.json",
+        description: "Index of the modpack",
+        handler: || get(mods::mod_pack_index),
+    },
+    RouteEntry {
+        path: "/modpack/manifest",
+        description: "Every file in the modpack with its size and download link",
+        handler: || get(mods::mod_pack_manifest),
+    },
+    RouteEntry {
+        path: "/modpack/diff",
+        description: "Mods that changed between two modpacks, e.g. /modpack/diff?from=2025.01.31&to=2025.02.28",
+        handler: || get(mods::mod_pack_diff),
+    },
+    RouteEntry {
+        path: "/modpack/{version}",
+        description: "A previously generated modpack, e.g. /modpack/2025.01.31.mrpack",
+        handler: || get(mods::mod_pack_snapshot),
+    },
+];
+
+#[derive(Debug, Serialize)]
+struct RouteInfo {
+    path: String,
+    description: &'static str,
+}
+
+/// Lists the routes that are being served.
+async fn routes(State(AppState { config, .. }): State<AppState>) -> Json<Vec<RouteInfo>> {
+    Json(route_index(&config))
+}
+
+fn route_index(config: &Config) -> Vec<RouteInfo> {
+    let route = |path: &str, description| RouteInfo {
+        path: format!("{}{path}", config.base_path()),
+        description,
+    };
+    let mut routes = ROUTES
+        .iter()
+        .map(|r| route(r.path, r.description))
+        .collect::<Vec<_>>();
+    if config.enabled_maps().next().is_some() {
+        routes.push(route("/maps", "Maps"));
+    }
+    routes.extend(
+        config
+            .enabled_maps()
+            .filter_map(|map| Some(route(map.route, map.name?))),
+    );
+    if config.static_dir.is_some() {
+        routes.push(route("/static", "Static assets"));
+    }
+    routes
+}
+
+/// Builds the full application router, independent of the listener it's served on.
+fn router(shared: SharedState) -> Router {
+    let config = &AppState::from_ref(&shared).config;
+    let base_path = config.base_path();
+    let (admin, public): (Vec<_>, Vec<_>) = ROUTES.iter().partition(|r| r.requires_auth());
+    let admin = admin
+        .into_iter()
+        .fold(Router::new(), |router, r| {
+            router.route(r.path, (r.handler)())
+        })
+        .route_layer(middleware::from_fn_with_state(
+            shared.clone(),
+            require_admin,
+        ));
+    let router = public
+        .into_iter()
+        .fold(
+            Router::new().nest_service("/favicon.ico", ServeFile::new(config.favicon())),
+            |router, r| router.route(r.path, (r.handler)()),
+        )
+        .merge(admin);
+    // only the routes, the maps and static assets are for browsing
+    let router = match config.allowed_origins.as_deref() {
+        Some(origins) if !origins.is_empty() => router.layer(
+            CorsLayer::new()
+                .allow_origin(AllowOrigin::list(
+                    origins.iter().filter_map(|o| HeaderValue::from_str(o).ok()),
+                ))
+                .allow_methods([Method::GET])
+                .allow_headers([ACCEPT, IF_NONE_MATCH])
+                .expose_headers([ETAG]),
+        ),
+        _ => router,
+    };
+    let router = add_map_routes(router, config);
+    let router = match &config.static_dir {
+        Some(static_dir) => {
+            tracing::info!(route = "/static", ?static_dir, "registering static assets");
+            router.nest_service("/static", ServeDir::new(static_dir))
+        }
+        None => router,
+    };
+    let router = match base_path {
+        "" => router,
+        base_path => Router::new()
+            .route(&format!("{base_path}/"), get(index))
+            .nest(base_path, router),
+    };
+    let router = router
+        .fallback(not_found)
+        .method_not_allowed_fallback(method_not_allowed)
+        .layer(RequestBodyLimitLayer::new(config.max_body_bytes()));
+    let router = match config.request_timeout_secs {
+        Some(secs) => router.layer(TimeoutLayer::with_status_code(
+            StatusCode::GATEWAY_TIMEOUT,
+            Duration::from_secs(secs),
+        )),
+        None => router,
+    };
+    router.with_state(shared)
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let config = get_configuration()?;
+    let config = Arc::new(get_configuration()?);
+    if std::env::args().any(|arg| arg == "--print-config") {
+        println!("{}", serde_json::to_string_pretty(&config.resolved()?)?);
+        return Ok(());
+    }
     init_tracing();
-    let router = Router::new()
-        .route("/", get(index))
-        .nest_service("/favicon.ico", ServeFile::new("./assets/favicon.ico"))
-        .route("/deaths", get(deaths::deaths))
-        .route("/mods", get(mods::get_mods))
-        .route("/mods/large-biomes.mrpack", get(mods::generate_mod_pack))
-        .route("/maps", get(maps))
-        .route("/super-secret-map/", get(Redirect::to("/maps/overworld/")))
-        .route("/super-secret-map", get(Redirect::to("/maps/overworld/")))
-        .route(
-            "/super-secret-map-nether/",
-            get(Redirect::to("/maps/nether/")),
-        )
-        .route(
-            "/super-secret-map-nether",
-            get(Redirect::to("/maps/nether/")),
-        );
-    let router = add_map_routes(
-        router,
-        &config,
-        &[
-            ("/maps/overworld/", "overworld-day"),
-            ("/maps/nether/", "nether"),
-            ("/super-secret-map-nether-mid/", "nether-mid"),
-        ],
-    );
-    let router = router.with_state(Arc::new(config));
+    let url = format!("http://localhost:50002{}/", config.base_path());
+    let refresh_interval = config.refresh_interval_secs;
+    let shared = SharedState(Arc::new(RwLock::new(AppState::new(config)?)));
+    if let Some(secs) = refresh_interval {
+        tokio::spawn(deaths::refresh(
+            shared.clone(),
+            Duration::from_secs(secs.max(1)),
+        ));
+    }
+    let router = router(shared);
 
-    println!("serving at http://localhost:50002");
+    println!("serving at {url}");
     axum::serve(
         tokio::net::TcpListener::bind("0.0.0.0:50002").await?,
-        router,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
     )
     .await?;
     Ok(())
@@ -108,28 +1040,290 @@ enum Error {
     Io(#[from] io::Error),
     #[error("serde: {0}")]
     Serde(#[from] serde_json::Error),
+    #[error("invalid json in {}: {source}", path.display())]
+    InvalidJson {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("invalid json from {url}: {source}")]
+    InvalidResponse {
+        url: String,
+        source: serde_json::Error,
+    },
     #[error("rendering: {0}")]
     Rendering(#[from] askama::Error),
+    #[error("rendering custom template: {0}")]
+    CustomRendering(#[from] minijinja::Error),
+    #[error("upstream: {0}")]
+    Upstream(reqwest::Error),
+    #[error("too many requests, retry in {retry_after}s")]
+    RateLimited { retry_after: u64 },
+    #[error("not found")]
+    NotFound,
+    #[error("bad request: {0}")]
+    BadRequest(String),
+    #[error("{url} doesn't match its hash")]
+    HashMismatch { url: String },
+    #[error("unauthorized")]
+    Unauthorized,
+    #[error("invalid config: {0}")]
+    InvalidConfig(#[from] config::ConfigError),
+    #[error("no neoforge version in {}", path.display())]
+    NoLoaderVersion { path: PathBuf },
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        // network failures and server errors are transient, anything else means we asked for
+        // something that doesn't exist
+        if e.is_connect() || e.is_timeout() || e.status().is_some_and(|s| s.is_server_error()) {
+            Self::Upstream(e)
+        } else {
+            Self::Io(io::Error::other(e))
+        }
+    }
 }
 
 impl IntoResponse for Error {
     fn into_response(self) -> axum::response::Response {
-        (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+        match self {
+            Self::Upstream(_) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                [(RETRY_AFTER, "60")],
+                self.to_string(),
+            )
+                .into_response(),
+            Self::RateLimited { retry_after } => (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(RETRY_AFTER, retry_after.to_string())],
+                self.to_string(),
+            )
+                .into_response(),
+            Self::NotFound => (StatusCode::NOT_FOUND, self.to_string()).into_response(),
+            Self::HashMismatch { .. } => {
+                (StatusCode::BAD_GATEWAY, self.to_string()).into_response()
+            }
+            Self::Unauthorized => (
+                StatusCode::UNAUTHORIZED,
+                [(WWW_AUTHENTICATE, r#"Basic realm="admin", charset="UTF-8""#)],
+                self.to_string(),
+            )
+                .into_response(),
+            Self::BadRequest(_) | Self::InvalidConfig(_) => {
+                (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+            }
+            _ => {
+                tracing::error!(error = %self, "request failed");
+                (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+            }
+        }
     }
 }
 
-#[derive(Debug, Template)]
+#[derive(Debug, Template, Serialize)]
 #[template(path = "index.html")]
-struct Index;
+struct Index<'a> {
+    base_path: &'a str,
+    #[serde(skip)]
+    locale: filters::Locale,
+    maps_enabled: bool,
+    total_deaths: usize,
+    players: usize,
+    loader_version: Option<String>,
+}
+
+impl Page for Index<'_> {
+    const OVERRIDE: Option<&'static str> = Some("index.html");
+}
+
+/// The home page, with a few stats that are left empty when they can't be computed so the links
+/// are always reachable.
+async fn index(State(state): State<AppState>) -> Result<impl IntoResponse, Error> {
+    let config = &state.config;
+    let total_deaths = deaths::total_deaths(&state).await;
+    let players = logs::players(config).map_or(0, |players| players.len());
+    let loader_version = mods::neoforge_version(config).await.ok();
+    Ok(Html(render(
+        config,
+        &Index {
+            base_path: config.base_path(),
+            locale: config.locale,
+            maps_enabled: config.enabled_maps().next().is_some(),
+            total_deaths,
+            players,
+            loader_version,
+        },
+    )?))
+}
+
+#[derive(Debug, Template, Serialize)]
+#[template(path = "not_found.html")]
+struct NotFound<'a> {
+    #[serde(skip)]
+    base_path: &'a str,
+    error: &'static str,
+    path: &'a str,
+}
+
+impl Page for NotFound<'_> {}
+
+/// Answers requests to unknown routes.
+async fn not_found(
+    State(AppState { config, .. }): State<AppState>,
+    headers: HeaderMap,
+    uri: OriginalUri,
+) -> Result<impl IntoResponse, Error> {
+    let page = NotFound {
+        base_path: config.base_path(),
+        error: "not found",
+        path: uri.path(),
+    };
+    Ok((StatusCode::NOT_FOUND, negotiate(&config, &headers, page)?))
+}
 
-async fn index() -> Result<impl IntoResponse, Error> {
-    Ok(Html(Index.render()?))
+#[derive(Debug, Template, Serialize)]
+#[template(path = "method_not_allowed.html")]
+struct MethodNotAllowed<'a> {
+    #[serde(skip)]
+    base_path: &'a str,
+    error: &'static str,
+    path: &'a str,
+    method: &'a str,
+}
+
+impl Page for MethodNotAllowed<'_> {}
+
+/// Answers requests to known routes with a method they don't handle, axum adds the `Allow` header.
+async fn method_not_allowed(
+    State(AppState { config, .. }): State<AppState>,
+    headers: HeaderMap,
+    method: Method,
+    uri: OriginalUri,
+) -> Result<impl IntoResponse, Error> {
+    let page = MethodNotAllowed {
+        base_path: config.base_path(),
+        error: "method not allowed",
+        path: uri.path(),
+        method: method.as_str(),
+    };
+    Ok((
+        StatusCode::METHOD_NOT_ALLOWED,
+        negotiate(&config, &headers, page)?,
+    ))
 }
 
 #[derive(Debug, Template)]
 #[template(path = "maps/index.html")]
-struct Maps;
+struct Maps<'a> {
+    base_path: &'a str,
+    maps: Vec<&'static MapRoute>,
+}
+
+async fn maps(State(AppState { config, .. }): State<AppState>) -> Result<impl IntoResponse, Error> {
+    Ok(Html(
+        Maps {
+            base_path: config.base_path(),
+            maps: config.enabled_maps().collect(),
+        }
+        .render()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{self, ServerDir, log_line};
+    use axum::http::StatusCode;
+    use serde_json::json;
+
+    /// A server whose deaths are spread over a rotated log and `latest.log`, which also has a
+    /// rotated copy as the newest rotated log.
+    fn server() -> ServerDir {
+        let server = ServerDir::new();
+        let latest = log_line("07Jun2025 12:00:00.000", "alice was slain by Zombie")
+            + &log_line("07Jun2025 12:01:00.000", "<alice> oops")
+            + &log_line("07Jun2025 12:02:00.000", "bob fell from a high place");
+        server
+            .whitelist(&["alice", "bob"])
+            .write_gz(
+                "logs/2025-06-05-1.log.gz",
+                log_line("05Jun2025 10:00:00.000", "alice was slain by Zombie")
+                    + &log_line("05Jun2025 10:05:00.000", "bob joined the game")
+                    + &log_line("05Jun2025 10:06:00.000", "bob drowned"),
+            )
+            .write_gz("logs/2025-06-07-1.log.gz", &latest)
+            .write("logs/latest.log", &latest);
+        server
+    }
 
-async fn maps() -> Result<impl IntoResponse, Error> {
-    Ok(Html(Maps.render()?))
+    #[tokio::test]
+    async fn deaths_counts_rotated_and_latest_logs() {
+        let server = server();
+        let (status, body) = server.get(server.config(json!({})), "/deaths").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total_deaths"], 4);
+        assert_eq!(body["unique_players"], 2);
+        let deaths = |name: &str| {
+            body["players"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|p| p["name"] == name)
+                .map(|p| p["total_deaths"].clone())
+        };
+        assert_eq!(deaths("alice"), Some(json!(2)));
+        assert_eq!(deaths("bob"), Some(json!(2)));
+        assert_eq!(body["unique_deaths"]["labels"][0], "was slain by Zombie");
+        assert_eq!(body["unique_deaths"]["values"][0], 2);
+    }
+
+    #[tokio::test]
+    async fn deaths_of_a_year_without_any() {
+        let server = server();
+        let (status, body) = server
+            .get(server.config(json!({})), "/deaths?year=2024")
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total_deaths"], 0);
+    }
+
+    #[tokio::test]
+    async fn reloading_forgets_what_was_parsed_with_the_old_config() {
+        let server = server();
+        let shared = testing::state(server.config(json!({})));
+        let (_, body) = testing::get(shared.clone(), "/deaths").await;
+        assert_eq!(body["unique_players"], 2);
+
+        let config = server.config(json!({ "ignored_players": ["bob"] }));
+        super::swap_config(&shared, config).await.unwrap();
+        let (_, body) = testing::get(shared, "/deaths").await;
+        assert_eq!(body["unique_players"], 1);
+        assert_eq!(body["total_deaths"], 2);
+    }
+
+    #[test]
+    fn date_label_format_has_to_tell_days_apart() {
+        let server = ServerDir::new();
+        let validate = |format: &str| {
+            serde_json::from_value::<super::Config>(json!({
+                "backups_dir": server.path(),
+                "server_dir": server.path(),
+                "date_label_format": format,
+            }))
+            .unwrap()
+            .validate()
+        };
+        assert!(validate("%Y-%m-%d").is_ok());
+        assert!(validate("%a %e %b %Y").is_ok());
+        assert!(validate("%d %b").is_err());
+        assert!(validate("%m/%Y").is_err());
+        assert!(validate("%Q").is_err());
+    }
+
+    #[tokio::test]
+    async fn unknown_route_is_not_found() {
+        let server = server();
+        let (status, body) = server.get(server.config(json!({})), "/nope").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["path"], "/nope");
+    }
 }
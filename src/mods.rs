@@ -1,52 +1,131 @@
-use crate::{Config, Error};
+use crate::{AppState, Config, Error, Page, etag_matches, filters, negotiate};
 use askama::Template;
 use axum::{
-    extract::State,
-    response::{AppendHeaders, Html, IntoResponse},
+    body::{Body, Bytes},
+    extract::{ConnectInfo, Path, Query, State},
+    http::HeaderMap,
+    response::{AppendHeaders, IntoResponse, Json, Response},
 };
 use regex::Regex;
 use reqwest::{StatusCode, header::CONTENT_TYPE};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use sha2::Digest as _;
 use std::{
+    collections::HashMap,
     io::{self, Cursor, Write},
-    sync::{Arc, LazyLock},
+    net::{IpAddr, SocketAddr},
+    path::{Path as FsPath, PathBuf},
+    sync::{
+        LazyLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Instant, SystemTime},
 };
 use tokio_stream::{StreamExt as _, wrappers::ReadDirStream};
+use tokio_util::io::ReaderStream;
+use utoipa::{IntoParams, ToSchema};
 use zip::write::SimpleFileOptions;
 
-#[derive(Debug, Default, Template)]
+#[derive(Debug, Default, Template, Serialize, ToSchema)]
 #[template(path = "mods/index.html")]
 pub struct Mods {
+    #[serde(skip)]
+    #[schema(ignore)]
+    base_path: String,
     neoforge_version: String,
     required: Vec<Mod>,
     recommended: Vec<Mod>,
     client_side: Vec<Mod>,
 }
 
-#[derive(Debug, Clone, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+impl Page for Mods {
+    const OVERRIDE: Option<&'static str> = Some("mods/index.html");
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, ToSchema)]
 pub struct Mod {
     name: String,
     slug: String,
     version: String,
     mandatory: bool,
     client_side_only: bool,
+    /// Modrinth's id of the project, which unlike the slug never changes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    project_id: Option<String>,
 }
 
 const LATEST: &str = "latest";
 
+pub use mod_pack::{FilePreference, LOADER, VersionType, cache_stats, clear_caches};
+
+/// How the entries of the `.mrpack` are compressed. Only the index and overrides are in the pack,
+/// the mods themselves are downloaded by the launcher.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    #[default]
+    Stored,
+    Deflated,
+}
+
+impl Compression {
+    fn options(self, level: Option<i64>) -> SimpleFileOptions {
+        let options = SimpleFileOptions::default();
+        match self {
+            Self::Stored => options.compression_method(zip::CompressionMethod::Stored),
+            Self::Deflated => options
+                .compression_method(zip::CompressionMethod::Deflated)
+                .compression_level(level),
+        }
+    }
+}
+
 mod mod_pack {
-    use crate::{Error, mods::Mod};
+    use crate::{CacheCounters, CacheStats, Config, Error, mods::Mod};
+    use chrono::{DateTime, TimeDelta, Utc};
     use futures::{StreamExt, TryStreamExt, io};
     use serde::{Deserialize, Serialize};
+    use sha2::{Digest as _, Sha256};
     use std::{
         collections::HashMap,
         sync::{LazyLock, Mutex},
-        time::{Duration, SystemTime},
     };
 
-    static MOD_INFO_CACHE: LazyLock<Mutex<HashMap<String, (SystemTime, Project)>>> =
+    /// A resolved project along with when it was resolved.
+    type CachedProject = (DateTime<Utc>, Project);
+
+    /// Resolved projects, by slug and version so swapping a mod's jar resolves it again.
+    static MOD_INFO_CACHE: LazyLock<Mutex<HashMap<(String, String), CachedProject>>> =
         LazyLock::new(Default::default);
 
+    static MOD_INFO_CACHE_COUNTERS: CacheCounters = CacheCounters::new();
+
+    pub fn cache_stats() -> CacheStats {
+        MOD_INFO_CACHE_COUNTERS.stats(MOD_INFO_CACHE.lock().unwrap().len())
+    }
+
+    /// Forgets every resolved project, which were resolved with the loaders and preferences of
+    /// the config of the moment.
+    pub fn clear_caches() {
+        MOD_INFO_CACHE.lock().unwrap().clear();
+        PROJECT_IDS.lock().unwrap().clear();
+    }
+
+    /// How long the resolution of a mod that tracks the latest version is reused for.
+    const LATEST_TTL: TimeDelta = TimeDelta::hours(72);
+
+    /// Source of the current time, so cache expiry and the pack version can be controlled.
+    pub type Clock = fn() -> DateTime<Utc>;
+
+    /// Whether a project of `version` cached at `cached_at` can still be used. Only the latest
+    /// version can change under the same key.
+    fn up_to_date(now: DateTime<Utc>, cached_at: DateTime<Utc>, version: &str) -> bool {
+        match version {
+            super::LATEST => (TimeDelta::zero()..LATEST_TTL).contains(&(now - cached_at)),
+            _ => true,
+        }
+    }
+
     #[derive(Debug, Serialize)]
     #[serde(rename_all = "camelCase")]
     pub struct ModPack {
@@ -63,121 +142,344 @@ mod mod_pack {
         pub async fn new(
             mods: impl Iterator<Item = Mod>,
             neoforge_version: String,
+            config: &Config,
+            client: &reqwest::Client,
+            clock: Clock,
         ) -> Result<Self, Error> {
-            let client = &reqwest::Client::new();
             Ok(Self {
                 game: "minecraft",
                 format_version: 1,
-                version_id: chrono::Utc::now()
-                    .date_naive()
-                    .format("%Y.%m.%d")
-                    .to_string(),
+                version_id: clock().date_naive().format("%Y.%m.%d").to_string(),
                 name: "large biomes pack",
                 summary: "the modpack for the large biomes server",
                 files: futures::stream::iter(mods)
-                    .map(|m| async move {
-                        let up_to_date = |ts: SystemTime, version: &str| match version {
-                            super::LATEST => match SystemTime::now().duration_since(ts) {
-                                Ok(d) => d < Duration::from_hours(72),
-                                Err(_) => false,
-                            },
-                            _ => m.version == version,
-                        };
-                        if let Some((ts, project)) = MOD_INFO_CACHE.lock().unwrap().get(&m.slug)
-                            && up_to_date(*ts, &project.version)
-                        {
-                            return Ok(project.clone());
-                        }
-                        let versions = async {
-                            tracing::info!(mod = ?m, "getting versions");
-                            client
-                                .get(format!(
-                                    "https://api.modrinth.com/v2/project/{}/version",
-                                    m.slug
-                                ))
-                                .send()
-                                .await?
-                                .error_for_status()?
-                                .json::<Vec<Version>>()
-                                .await
-                        }
-                        .await
-                        .map_err(io::Error::other)?;
-
-                        #[derive(Deserialize)]
-                        struct Version {
-                            game_versions: Vec<String>,
-                            loaders: Vec<String>,
-                            version_number: String,
-                            files: Vec<VersionFile>,
-                        }
-
-                        #[derive(Deserialize)]
-                        struct VersionFile {
-                            hashes: Hashes,
-                            url: String,
-                            filename: String,
-                            size: usize,
-                            primary: bool,
-                        }
-
-                        let Some(version) = versions.into_iter().find(|v| {
-                            v.loaders.iter().any(|l| l == "neoforge")
-                                && v.game_versions.iter().any(|l| l == "1.21.1")
-                                && (m.client_side_only || v.version_number.contains(&m.version))
-                        }) else {
-                            tracing::error!(mod = ?m, "failed to find suitable version");
-                            return Err(Error::Io(io::Error::other(format!(
-                                "failed to find suitable version for mod: {}",
-                                m.name
-                            ))));
-                        };
-
-                        let file_idx = version
-                            .files
-                            .iter()
-                            .position(|f| f.primary)
-                            .unwrap_or_default();
-
-                        let Some(file) = version.files.into_iter().nth(file_idx) else {
-                            tracing::error!(mod = ?m, "failed to find suitable file");
-                            return Err(Error::Io(io::Error::other(format!(
-                                "failed to find suitable file for mod: {}",
-                                m.name
-                            ))));
-                        };
-
-                        let project = Project {
-                            path: format!("mods/{}", file.filename),
-                            hashes: file.hashes,
-                            env: Env {
-                                client: if m.mandatory { "required" } else { "optional" },
-                            },
-                            downloads: vec![file.url],
-                            file_size: file.size,
-                            version: m.version,
-                        };
-                        MOD_INFO_CACHE
-                            .lock()
-                            .unwrap()
-                            .insert(m.slug.clone(), (SystemTime::now(), project.clone()));
-                        Ok(project)
-                    })
+                    .map(|m| resolve_mod(client, m, config, clock))
                     .buffered(usize::MAX)
                     .try_collect()
                     .await?,
                 dependencies: Dependencies {
-                    minecraft: "1.21.1".to_owned(),
+                    minecraft: GAME_VERSION.to_owned(),
                     neoforge: neoforge_version,
                 },
             })
         }
+
+        /// An etag that changes whenever the files or what they run on do, but not with the day
+        /// in `version_id`.
+        pub fn etag(&self) -> String {
+            let mut hasher = Sha256::new();
+            let Dependencies {
+                minecraft,
+                neoforge,
+            } = &self.dependencies;
+            let hashes = self.files.iter().flat_map(|f| {
+                [
+                    Some(&f.path),
+                    f.hashes.sha512.as_ref(),
+                    f.hashes.sha1.as_ref(),
+                ]
+            });
+            for field in [Some(minecraft), Some(neoforge)].into_iter().chain(hashes) {
+                // separated so moving bytes from one field to the next changes the etag
+                hasher.update(field.map_or("", String::as_str));
+                hasher.update([0]);
+            }
+            format!("\"{}\"", super::hex(&hasher.finalize()[..16]))
+        }
+    }
+
+    const GAME_VERSION: &str = "1.21.1";
+    /// The server's mod loader, the only one mods are accepted for unless `loaders` is set.
+    pub const LOADER: &str = "neoforge";
+
+    /// A property that makes a version file preferable over others. Files are ranked by each
+    /// preference in order, the first preference that tells two files apart decides.
+    #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum FilePreference {
+        /// Files flagged as primary by the mod author.
+        Primary,
+        /// Files that are jars.
+        Jar,
+        /// Files that aren't sources, dev or api jars.
+        NotAuxiliary,
+        /// Files whose name mentions the minecraft version.
+        GameVersion,
+        /// Files whose name mentions the mod loader.
+        Loader,
+    }
+
+    impl FilePreference {
+        pub const DEFAULT: &[Self] = &[
+            Self::Jar,
+            Self::NotAuxiliary,
+            Self::Primary,
+            Self::GameVersion,
+            Self::Loader,
+        ];
+
+        fn matches(self, file: &VersionFile, loader: &str) -> bool {
+            let name = file.filename.to_lowercase();
+            match self {
+                Self::Primary => file.primary,
+                Self::Jar => name.ends_with(".jar"),
+                Self::NotAuxiliary => !["-sources", "-dev", "-api"]
+                    .iter()
+                    .any(|suffix| name.contains(suffix)),
+                Self::GameVersion => name.contains(GAME_VERSION),
+                Self::Loader => name.contains(loader),
+            }
+        }
+    }
+
+    /// The project id each slug resolved to the first time, to notice when a slug changes hands.
+    static PROJECT_IDS: LazyLock<Mutex<HashMap<String, String>>> = LazyLock::new(Default::default);
+
+    /// How stable a version is, from least to most.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum VersionType {
+        Alpha,
+        Beta,
+        Release,
+    }
+
+    #[derive(Deserialize)]
+    struct Version {
+        project_id: String,
+        version_type: VersionType,
+        game_versions: Vec<String>,
+        loaders: Vec<String>,
+        version_number: String,
+        files: Vec<VersionFile>,
+    }
+
+    impl Version {
+        /// The position in `loaders` of the most preferred one this version is built for, when it
+        /// is built for the server's minecraft version.
+        fn loader_rank(&self, loaders: &[&str]) -> Option<usize> {
+            if !self.game_versions.iter().any(|l| l == GAME_VERSION) {
+                return None;
+            }
+            loaders
+                .iter()
+                .position(|loader| self.loaders.iter().any(|l| l == loader))
+        }
+
+        /// Whether this version runs on one of `loaders` and the server's minecraft version.
+        fn is_compatible(&self, loaders: &[&str]) -> bool {
+            self.loader_rank(loaders).is_some()
+        }
+    }
+
+    /// Whether `version` is the version of the mod that was asked for.
+    fn wanted(m: &Mod, version: &Version) -> bool {
+        m.client_side_only || version.version_number.contains(&m.version)
+    }
+
+    /// Explains why none of the versions of a mod could be used, so admins can tell a mod that is
+    /// lagging behind the server's version from a typo in its version.
+    fn mismatch(m: &Mod, versions: &[Version], loaders: &[&str], min: VersionType) -> String {
+        if let Some(v) = versions
+            .iter()
+            .find(|v| v.is_compatible(loaders) && wanted(m, v))
+        {
+            return format!(
+                "{} is {:?}, which is less stable than the minimum of {min:?}",
+                v.version_number, v.version_type
+            );
+        }
+        let Some(requested) = versions.iter().find(|v| wanted(m, v)) else {
+            return format!("there is no version {} on modrinth", m.version);
+        };
+        let nearest = match versions.iter().find(|v| v.is_compatible(loaders)) {
+            Some(v) => format!("the newest build that is, is {}", v.version_number),
+            None => "no version of it is yet".to_owned(),
+        };
+        format!(
+            "{} has no build for {} on minecraft {GAME_VERSION} (only {} on {}), {nearest}",
+            requested.version_number,
+            loaders.join(" or "),
+            requested.loaders.join(", "),
+            requested.game_versions.join(", "),
+        )
+    }
+
+    #[derive(Deserialize)]
+    struct VersionFile {
+        hashes: Hashes,
+        url: String,
+        filename: String,
+        size: usize,
+        primary: bool,
+    }
+
+    /// The file of a version that goes in the pack, the best one by `preferences` and the first
+    /// listed of equally good ones.
+    fn pick_file(
+        files: Vec<VersionFile>,
+        preferences: &[FilePreference],
+        loader: &str,
+    ) -> Option<VersionFile> {
+        let best = files
+            .iter()
+            .enumerate()
+            .max_by_key(|(i, f)| {
+                let ranks = preferences
+                    .iter()
+                    .map(|p| p.matches(f, loader))
+                    .collect::<Vec<_>>();
+                (ranks, std::cmp::Reverse(*i))
+            })
+            .map(|(i, _)| i)?;
+        files.into_iter().nth(best)
+    }
+
+    /// Resolves every mod, without stopping at the first failure.
+    pub async fn resolve_all(
+        mods: impl Iterator<Item = Mod>,
+        config: &Config,
+        client: &reqwest::Client,
+        clock: Clock,
+    ) -> Vec<(Mod, Result<Project, Error>)> {
+        futures::stream::iter(mods)
+            .map(|m| async move {
+                let resolved = resolve_mod(client, m.clone(), config, clock).await;
+                (m, resolved)
+            })
+            .buffered(usize::MAX)
+            .collect()
+            .await
+    }
+
+    /// Finds the file to download for a mod on modrinth.
+    pub async fn resolve_mod(
+        client: &reqwest::Client,
+        m: Mod,
+        config: &Config,
+        clock: Clock,
+    ) -> Result<Project, Error> {
+        let key = (m.slug.clone(), m.version.clone());
+        if let Some((ts, project)) = MOD_INFO_CACHE.lock().unwrap().get(&key)
+            && up_to_date(clock(), *ts, &project.version)
+        {
+            MOD_INFO_CACHE_COUNTERS.hit();
+            return Ok(project.clone());
+        }
+        MOD_INFO_CACHE_COUNTERS.miss();
+        let url = format!(
+            "{}/project/{}/version",
+            config.modrinth_base_url(),
+            m.project_id.as_ref().unwrap_or(&m.slug)
+        );
+        tracing::info!(mod = ?m, "getting versions");
+        let body = client
+            .get(&url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        let mut versions = serde_json::from_slice::<Vec<Version>>(&body)
+            .map_err(|source| Error::InvalidResponse { url, source })?;
+
+        if let Some(resolved) = versions.first().map(|v| &v.project_id) {
+            let mut project_ids = PROJECT_IDS.lock().unwrap();
+            let expected = m
+                .project_id
+                .as_ref()
+                .or_else(|| project_ids.get(&m.slug))
+                .cloned();
+            match expected {
+                Some(expected) if expected != *resolved => tracing::warn!(
+                    mod = ?m,
+                    expected,
+                    resolved,
+                    "project resolved to a different id, was it renamed?"
+                ),
+                Some(_) => {}
+                None => {
+                    project_ids.insert(m.slug.clone(), resolved.clone());
+                }
+            }
+        }
+
+        let min = config.min_version_type();
+        let loaders = config.loaders();
+        let Some(version) = versions
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| v.is_compatible(&loaders) && wanted(&m, v) && v.version_type >= min)
+            // modrinth lists the newest first, take the newest of the most stable, preferring the
+            // earlier loaders
+            .max_by_key(|(i, v)| {
+                (
+                    v.version_type,
+                    std::cmp::Reverse(v.loader_rank(&loaders)),
+                    std::cmp::Reverse(*i),
+                )
+            })
+            .map(|(i, _)| i)
+            .map(|i| versions.swap_remove(i))
+        else {
+            let reason = mismatch(&m, &versions, &loaders, min);
+            tracing::warn!(mod = ?m, reason, "failed to find suitable version");
+            return Err(Error::Io(io::Error::other(format!(
+                "failed to find suitable version for mod {}: {reason}",
+                m.name
+            ))));
+        };
+        tracing::info!(
+            mod = ?m,
+            version = version.version_number,
+            version_type = ?version.version_type,
+            "picked version"
+        );
+        if version.loader_rank(&loaders) != Some(0) {
+            tracing::info!(
+                mod = ?m,
+                loaders = ?version.loaders,
+                "accepted through a fallback loader"
+            );
+        }
+
+        let Some(file) = pick_file(version.files, config.file_preference(), loaders[0]) else {
+            tracing::error!(mod = ?m, "failed to find suitable file");
+            return Err(Error::Io(io::Error::other(format!(
+                "failed to find suitable file for mod: {}",
+                m.name
+            ))));
+        };
+
+        if file.hashes.sha512.is_none() && file.hashes.sha1.is_none() {
+            tracing::error!(mod = ?m, file = file.filename, "file has no hashes");
+            return Err(Error::Io(io::Error::other(format!(
+                "file {} of mod {} has no hashes",
+                file.filename, m.name
+            ))));
+        }
+
+        let project = Project {
+            path: format!("mods/{}", file.filename),
+            hashes: file.hashes,
+            env: Env {
+                client: if m.mandatory { "required" } else { "optional" },
+            },
+            downloads: vec![file.url],
+            file_size: file.size,
+            version: m.version,
+        };
+        MOD_INFO_CACHE
+            .lock()
+            .unwrap()
+            .insert(key, (clock(), project.clone()));
+        Ok(project)
     }
 
     #[derive(Debug, Clone, Serialize)]
     #[serde(rename_all = "camelCase")]
     pub struct Project {
-        path: String,
+        pub path: String,
         hashes: Hashes,
         env: Env,
         downloads: Vec<String>,
@@ -186,10 +488,33 @@ mod mod_pack {
         version: String,
     }
 
+    impl Project {
+        /// Size of the file in bytes.
+        pub fn file_size(&self) -> usize {
+            self.file_size
+        }
+
+        /// Where the launcher downloads the file from, resolved projects have exactly one.
+        pub fn download(&self) -> &str {
+            &self.downloads[0]
+        }
+
+        pub fn sha512(&self) -> Option<&str> {
+            self.hashes.sha512.as_deref()
+        }
+
+        pub fn sha1(&self) -> Option<&str> {
+            self.hashes.sha1.as_deref()
+        }
+    }
+
+    /// Modrinth very rarely leaves one of them out, the pack has whichever are known.
     #[derive(Debug, Serialize, Deserialize, Clone)]
     pub struct Hashes {
-        sha512: String,
-        sha1: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sha512: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sha1: Option<String>,
     }
 
     #[derive(Debug, Clone, Serialize)]
@@ -202,19 +527,645 @@ mod mod_pack {
         minecraft: String,
         neoforge: String,
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn pack(version_id: &str, files: &[(&str, &str)]) -> ModPack {
+            ModPack {
+                game: "minecraft",
+                format_version: 1,
+                version_id: version_id.to_owned(),
+                name: "large biomes pack",
+                summary: "",
+                files: files
+                    .iter()
+                    .map(|(path, sha1)| Project {
+                        path: path.to_string(),
+                        hashes: Hashes {
+                            sha512: None,
+                            sha1: Some(sha1.to_string()),
+                        },
+                        env: Env { client: "required" },
+                        downloads: vec![],
+                        file_size: 0,
+                        version: String::new(),
+                    })
+                    .collect(),
+                dependencies: Dependencies {
+                    minecraft: GAME_VERSION.to_owned(),
+                    neoforge: "21.1.1".to_owned(),
+                },
+            }
+        }
+
+        #[test]
+        fn etag_only_changes_with_the_files() {
+            let files = [("mods/a.jar", "aa"), ("mods/b.jar", "bb")];
+            let etag = pack("2025.06.05", &files).etag();
+            assert_eq!(pack("2025.06.06", &files).etag(), etag);
+            assert_ne!(
+                pack("2025.06.05", &[("mods/a.jar", "aa"), ("mods/b.jar", "bc")]).etag(),
+                etag
+            );
+            assert_ne!(
+                pack("2025.06.05", &[("mods/a.jar", "aa"), ("mods/c.jar", "bb")]).etag(),
+                etag
+            );
+            assert_ne!(pack("2025.06.05", &files[..1]).etag(), etag);
+        }
+
+        fn at(rfc3339: &str) -> DateTime<Utc> {
+            rfc3339.parse().unwrap()
+        }
+
+        #[test]
+        fn latest_is_up_to_date_for_72_hours() {
+            let cached_at = at("2025-06-05T12:00:00Z");
+            let up_to_date = |now| up_to_date(at(now), cached_at, crate::mods::LATEST);
+            assert!(up_to_date("2025-06-05T12:00:00Z"));
+            assert!(up_to_date("2025-06-08T11:59:59Z"));
+            assert!(!up_to_date("2025-06-08T12:00:00Z"));
+            // a clock that went back doesn't keep it forever
+            assert!(!up_to_date("2025-06-05T11:59:59Z"));
+        }
+
+        #[test]
+        fn pinned_versions_are_always_up_to_date() {
+            let cached_at = at("2025-06-05T12:00:00Z");
+            assert!(up_to_date(at("2026-06-05T12:00:00Z"), cached_at, "1.2.3"));
+        }
+
+        fn files(names: &[(&str, bool)]) -> Vec<VersionFile> {
+            names
+                .iter()
+                .map(|(filename, primary)| VersionFile {
+                    hashes: Hashes {
+                        sha512: None,
+                        sha1: Some("aa".to_owned()),
+                    },
+                    url: format!("https://cdn.modrinth.com/{filename}"),
+                    filename: filename.to_string(),
+                    size: 0,
+                    primary: *primary,
+                })
+                .collect()
+        }
+
+        fn picked(names: &[(&str, bool)], preferences: &[FilePreference]) -> Option<String> {
+            pick_file(files(names), preferences, LOADER).map(|f| f.filename)
+        }
+
+        #[test]
+        fn picks_files_by_preference_in_order() {
+            let names = [
+                ("mod-1.0-sources.jar", true),
+                ("mod-1.0.zip", false),
+                ("mod-1.0-forge.jar", false),
+                ("mod-1.0-neoforge-1.21.1.jar", false),
+                ("mod-1.0-neoforge.jar", false),
+            ];
+            // the primary file is a sources jar, which the default ranks below the others
+            assert_eq!(
+                picked(&names, FilePreference::DEFAULT).as_deref(),
+                Some("mod-1.0-neoforge-1.21.1.jar")
+            );
+            assert_eq!(
+                picked(&names, &[FilePreference::Primary]).as_deref(),
+                Some("mod-1.0-sources.jar")
+            );
+            assert_eq!(
+                picked(&names, &[FilePreference::Jar, FilePreference::Loader]).as_deref(),
+                Some("mod-1.0-neoforge-1.21.1.jar")
+            );
+            // ties go to the first listed
+            assert_eq!(
+                picked(&names, &[FilePreference::NotAuxiliary]).as_deref(),
+                Some("mod-1.0.zip")
+            );
+            assert_eq!(picked(&[], FilePreference::DEFAULT), None);
+        }
+
+        #[test]
+        fn version_files_without_a_sha512() {
+            let file = serde_json::from_value::<VersionFile>(serde_json::json!({
+                "hashes": { "sha1": "aa" },
+                "url": "https://cdn.modrinth.com/mod.jar",
+                "filename": "mod.jar",
+                "size": 3,
+                "primary": true,
+            }))
+            .unwrap();
+            assert_eq!(file.hashes.sha512, None);
+            assert_eq!(file.hashes.sha1.as_deref(), Some("aa"));
+            // and the pack doesn't list the missing hash
+            assert_eq!(
+                serde_json::to_value(&file.hashes).unwrap(),
+                serde_json::json!({ "sha1": "aa" })
+            );
+        }
+
+        fn version(game_version: &str, loaders: &[&str]) -> Version {
+            Version {
+                project_id: "AANobbMI".to_owned(),
+                version_type: VersionType::Release,
+                game_versions: vec![game_version.to_owned()],
+                loaders: loaders.iter().map(|l| l.to_string()).collect(),
+                version_number: "1.0".to_owned(),
+                files: vec![],
+            }
+        }
+
+        #[test]
+        fn forge_only_mods_rank_by_where_forge_is_accepted() {
+            let forge = version(GAME_VERSION, &["forge"]);
+            assert_eq!(forge.loader_rank(&[LOADER]), None);
+            assert!(!forge.is_compatible(&[LOADER]));
+            assert_eq!(forge.loader_rank(&[LOADER, "forge"]), Some(1));
+            assert_eq!(forge.loader_rank(&["forge", LOADER]), Some(0));
+            // the loader doesn't help a version for another minecraft version
+            assert_eq!(version("1.20.1", &["forge"]).loader_rank(&["forge"]), None);
+        }
+
+        #[tokio::test]
+        async fn version_id_is_the_day_of_the_clock() {
+            let server = crate::testing::ServerDir::new();
+            let config = server.config(serde_json::json!({}));
+            let modpack = ModPack::new(
+                std::iter::empty(),
+                "21.1.1".to_owned(),
+                &config,
+                &reqwest::Client::new(),
+                || at("2025-06-05T23:59:59Z"),
+            )
+            .await
+            .unwrap();
+            assert_eq!(modpack.version_id, "2025.06.05");
+        }
+    }
 }
 
-pub async fn generate_mod_pack(config: State<Arc<Config>>) -> Result<impl IntoResponse, Error> {
-    let server_mods = server_mods(&config).await?;
-    let recommended_mods = recommended_mods().await?;
-    let neoforge_version = neoforge_version(&config).await?;
-    let modpack = mod_pack::ModPack::new(
+async fn build_mod_pack(
+    config: &Config,
+    client: &reqwest::Client,
+) -> Result<mod_pack::ModPack, Error> {
+    let server_mods = server_mods(config).await?;
+    let recommended_mods = recommended_mods(config).await?;
+    let neoforge_version = neoforge_version(config).await?;
+    mod_pack::ModPack::new(
         server_mods.into_iter().chain(recommended_mods),
         neoforge_version,
+        config,
+        client,
+        chrono::Utc::now,
     )
-    .await?;
+    .await
+}
+
+#[derive(Debug, Serialize)]
+struct ModValidation {
+    name: String,
+    slug: String,
+    version: String,
+    /// Path of the resolved file within the pack.
+    file: Option<String>,
+    error: Option<String>,
+}
+
+pub async fn validate_mods(
+    State(AppState { config, http, .. }): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> Result<impl IntoResponse, Error> {
+    // resolving every mod costs as many requests to modrinth as generating the pack
+    rate_limit_mod_pack(&config, addr.ip())?;
+    let mods = server_mods(&config)
+        .await?
+        .into_iter()
+        .chain(recommended_mods(&config).await?);
+    let report = mod_pack::resolve_all(mods, &config, &http, chrono::Utc::now)
+        .await
+        .into_iter()
+        .map(|(m, resolved)| {
+            let (file, error) = match resolved {
+                Ok(project) => (Some(project.path), None),
+                Err(e) => (None, Some(e.to_string())),
+            };
+            ModValidation {
+                name: m.name,
+                slug: m.slug,
+                version: m.version,
+                file,
+                error,
+            }
+        })
+        .collect::<Vec<_>>();
+    Ok(Json(report))
+}
+
+#[derive(Debug, Serialize)]
+struct ManifestEntry {
+    name: String,
+    version: String,
+    /// Name of the jar, without the `mods/` directory.
+    file: String,
+    size: usize,
+    url: String,
+}
+
+#[derive(Debug, Template, Serialize)]
+#[template(path = "mods/manifest.html")]
+pub struct Manifest {
+    #[serde(skip)]
+    base_path: String,
+    entries: Vec<ManifestEntry>,
+    /// Sum of the sizes of every file, what downloading the whole pack costs.
+    total_size: usize,
+}
+
+impl Page for Manifest {}
+
+/// A shareable listing of what's in the modpack, resolved the same way as the pack itself.
+pub async fn mod_pack_manifest(
+    State(AppState { config, http, .. }): State<AppState>,
+    headers: HeaderMap,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> Result<impl IntoResponse, Error> {
+    rate_limit_mod_pack(&config, addr.ip())?;
+    let mods = server_mods(&config)
+        .await?
+        .into_iter()
+        .chain(recommended_mods(&config).await?);
+    let entries = mod_pack::resolve_all(mods, &config, &http, chrono::Utc::now)
+        .await
+        .into_iter()
+        .map(|(m, resolved)| {
+            let project = resolved?;
+            Ok(ManifestEntry {
+                name: m.name,
+                version: m.version,
+                file: project
+                    .path
+                    .strip_prefix("mods/")
+                    .unwrap_or(&project.path)
+                    .to_owned(),
+                size: project.file_size(),
+                url: project.download().to_owned(),
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let manifest = Manifest {
+        base_path: config.base_path().to_owned(),
+        total_size: entries.iter().map(|e| e.size).sum(),
+        entries,
+    };
+    Ok((
+        config.cache_headers(None),
+        negotiate(&config, &headers, manifest)?,
+    ))
+}
+
+/// Clients' token buckets for the modpack and validation routes, with when they were last refilled.
+static MODPACK_BUCKETS: LazyLock<std::sync::Mutex<HashMap<IpAddr, (Instant, f64)>>> =
+    LazyLock::new(Default::default);
+
+/// Takes a token from the client's bucket, failing with how long until there is one.
+fn rate_limit_mod_pack(config: &Config, ip: IpAddr) -> Result<(), Error> {
+    let Some(per_minute) = config.modpack_rate_limit else {
+        return Ok(());
+    };
+    let capacity = f64::from(per_minute);
+    let rate = capacity / 60.0;
+    let now = Instant::now();
+    let refilled = |last: Instant, tokens: f64| {
+        (tokens + now.duration_since(last).as_secs_f64() * rate).min(capacity)
+    };
+    let mut buckets = MODPACK_BUCKETS.lock().unwrap();
+    // full buckets are the same as missing ones
+    buckets.retain(|_, (last, tokens)| refilled(*last, *tokens) < capacity);
+    let (last, tokens) = buckets.entry(ip).or_insert((now, capacity));
+    *tokens = refilled(*last, *tokens);
+    *last = now;
+    if *tokens >= 1.0 {
+        *tokens -= 1.0;
+        Ok(())
+    } else {
+        Err(Error::RateLimited {
+            retry_after: ((1.0 - *tokens) / rate).ceil() as u64,
+        })
+    }
+}
+
+pub async fn mod_pack_index(
+    State(AppState { config, http, .. }): State<AppState>,
+    headers: HeaderMap,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> Result<Response, Error> {
+    rate_limit_mod_pack(&config, addr.ip())?;
+    let modpack = build_mod_pack(&config, &http).await?;
+    let etag = modpack.etag();
+    let cache_headers = config.cache_headers(Some(&etag));
+    if etag_matches(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+    }
+    Ok((cache_headers, Json(modpack)).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ModPackQuery {
+    /// Whether the jars go in the pack, for offline installs, instead of being downloaded by
+    /// the launcher.
+    #[serde(default)]
+    embed: bool,
+}
+
+pub async fn generate_mod_pack(
+    State(AppState { config, http, .. }): State<AppState>,
+    headers: HeaderMap,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(ModPackQuery { embed }): Query<ModPackQuery>,
+) -> Result<Response, Error> {
+    rate_limit_mod_pack(&config, addr.ip())?;
+    let modpack = build_mod_pack(&config, &http).await?;
     let json_data = serde_json::to_vec_pretty(&modpack).unwrap();
+    let etag = modpack.etag();
+    let cache_headers = config.cache_headers(Some(&etag));
+    if etag_matches(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+    }
+
+    let snapshot = config.snapshot_path(&modpack.version_id);
+    if let Err(e) = save_snapshot(&snapshot, &json_data).await {
+        tracing::warn!(error = ?e, ?snapshot, "failed to save modpack snapshot");
+    }
+    if embed {
+        let path = config.embedded_pack_path(&etag);
+        if !tokio::fs::try_exists(&path).await? {
+            embedded_mrpack(&config, &http, modpack, &path).await?;
+        }
+        let file = tokio::fs::File::open(&path).await?;
+        return Ok((
+            cache_headers,
+            [(CONTENT_TYPE, "application/x-modrith-modpack+zip")],
+            Body::from_stream(ReaderStream::new(file)),
+        )
+            .into_response());
+    }
+    Ok((cache_headers, mrpack(&config, &json_data).await?).into_response())
+}
+
+/// Writes a modpack with the jars in its overrides to `path`, which is only created once the
+/// pack is complete.
+async fn embedded_mrpack(
+    config: &Config,
+    http: &reqwest::Client,
+    modpack: mod_pack::ModPack,
+    path: &FsPath,
+) -> Result<(), Error> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    // concurrent requests for the same pack each write their own
+    static PARTIALS: AtomicU64 = AtomicU64::new(0);
+    let partial = path.with_extension(format!("{}.part", PARTIALS.fetch_add(1, Ordering::Relaxed)));
+    match write_embedded_mrpack(config, http, modpack, &partial).await {
+        Ok(()) => {
+            tokio::fs::rename(&partial, path).await?;
+            tracing::info!(?path, "saved modpack with embedded jars");
+            prune_embedded_packs(path).await;
+            Ok(())
+        }
+        Err(e) => {
+            if let Err(e) = tokio::fs::remove_file(&partial).await {
+                tracing::warn!(error = ?e, ?partial, "failed to remove partial modpack");
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Removes the packs with embedded jars other than `latest`, which are never served again.
+async fn prune_embedded_packs(latest: &FsPath) {
+    let Some(dir) = latest.parent() else {
+        return;
+    };
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            tracing::warn!(error = ?e, ?dir, "failed to list embedded modpacks");
+            return;
+        }
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        if path != latest
+            && path.extension().is_some_and(|e| e == "mrpack")
+            && let Err(e) = tokio::fs::remove_file(&path).await
+        {
+            tracing::warn!(error = ?e, ?path, "failed to remove old embedded modpack");
+        }
+    }
+}
+
+/// What is written to the pack, in order.
+enum Entry {
+    /// Starts a file at this path of the pack.
+    File(String),
+    /// More of the file that was started last.
+    Chunk(Bytes),
+}
+
+/// Each jar is streamed into the pack as it's downloaded and checked against its hashes. The
+/// pack is written on a blocking thread, which is sent the entries through a channel.
+async fn write_embedded_mrpack(
+    config: &Config,
+    http: &reqwest::Client,
+    mut modpack: mod_pack::ModPack,
+    path: &FsPath,
+) -> Result<(), Error> {
+    let options = config
+        .modpack_compression
+        .unwrap_or_default()
+        .options(config.modpack_compression_level);
+    let (entries, mut received) = tokio::sync::mpsc::channel(16);
+    let writer = tokio::task::spawn_blocking({
+        let path = path.to_owned();
+        move || -> Result<(), Error> {
+            let mut zip = zip::ZipWriter::new(std::fs::File::create(path)?);
+            while let Some(entry) = received.blocking_recv() {
+                match entry {
+                    Entry::File(name) => zip.start_file(name, options).map_err(io::Error::other)?,
+                    Entry::Chunk(chunk) => zip.write_all(&chunk)?,
+                }
+            }
+            zip.finish().map_err(io::Error::other)?;
+            Ok(())
+        }
+    });
+    let send = async |entry| {
+        entries
+            .send(entry)
+            .await
+            // the writer stopped, its error is the one returned
+            .map_err(|_| Error::Io(io::ErrorKind::BrokenPipe.into()))
+    };
+    let sent = async {
+        // the launcher would download again whatever is listed
+        let files = std::mem::take(&mut modpack.files);
+        send(Entry::File("modrinth.index.json".to_owned())).await?;
+        send(Entry::Chunk(serde_json::to_vec_pretty(&modpack)?.into())).await?;
+        send(Entry::File("overrides/servers.dat".to_owned())).await?;
+        send(Entry::Chunk(
+            tokio::fs::read("./assets/servers.dat").await?.into(),
+        ))
+        .await?;
+        for project in files {
+            let url = project.download();
+            send(Entry::File(format!("overrides/{}", project.path))).await?;
+            let mut response = http.get(url).send().await?.error_for_status()?;
+            let mut sha512 = sha2::Sha512::new();
+            let mut sha1 = sha1::Sha1::new();
+            while let Some(chunk) = response.chunk().await? {
+                sha512.update(&chunk);
+                sha1.update(&chunk);
+                send(Entry::Chunk(chunk)).await?;
+            }
+            let matches = |expected: Option<&str>, actual: &[u8]| {
+                expected.is_none_or(|e| e.eq_ignore_ascii_case(&hex(actual)))
+            };
+            if !matches(project.sha512(), &sha512.finalize())
+                || !matches(project.sha1(), &sha1.finalize())
+            {
+                return Err(Error::HashMismatch {
+                    url: url.to_owned(),
+                });
+            }
+        }
+        Ok(())
+    }
+    .await;
+    // closing the channel lets the writer finish
+    drop(entries);
+    writer.await.unwrap().and(sent)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+async fn save_snapshot(path: &FsPath, index: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    tokio::fs::write(path, index).await
+}
+
+/// Serves a modpack exactly as it was generated on a previous day, so a download link doesn't
+/// change when a mod that tracks the latest version updates.
+pub async fn mod_pack_snapshot(
+    State(AppState { config, .. }): State<AppState>,
+    Path(file): Path<String>,
+) -> Result<impl IntoResponse, Error> {
+    let version_id = file.strip_suffix(".mrpack").ok_or(Error::NotFound)?;
+    let json_data = read_snapshot(&config, version_id).await?;
+    mrpack(&config, &json_data).await
+}
+
+/// Reads the index of a previously generated modpack.
+async fn read_snapshot(config: &Config, version_id: &str) -> Result<Vec<u8>, Error> {
+    // the version is used as a file name
+    if version_id.starts_with('.')
+        || !version_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+    {
+        return Err(Error::NotFound);
+    }
+    match tokio::fs::read(config.snapshot_path(version_id)).await {
+        Ok(json_data) => Ok(json_data),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err(Error::NotFound),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SnapshotIndex {
+    files: Vec<SnapshotFile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SnapshotFile {
+    path: String,
+    downloads: Vec<String>,
+}
+
+impl SnapshotFile {
+    /// What identifies the mod across versions, the modrinth project of the download or else the
+    /// path.
+    fn project(&self) -> &str {
+        static REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"/data/([^/]+)/").unwrap());
+        self.downloads
+            .iter()
+            .find_map(|url| REGEX.captures(url))
+            .and_then(|captures| captures.get(1))
+            .map_or(&self.path, |project| project.as_str())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DiffQuery {
+    from: String,
+    to: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct ModPackDiff {
+    added: Vec<String>,
+    removed: Vec<String>,
+    changed: Vec<ChangedMod>,
+}
 
+#[derive(Debug, Serialize)]
+struct ChangedMod {
+    from: String,
+    to: String,
+}
+
+/// The mods that changed between two previously generated modpacks, by their path in the pack.
+pub async fn mod_pack_diff(
+    State(AppState { config, .. }): State<AppState>,
+    Query(DiffQuery { from, to }): Query<DiffQuery>,
+) -> Result<impl IntoResponse, Error> {
+    let read = async |version_id| {
+        let path = config.snapshot_path(version_id);
+        serde_json::from_slice::<SnapshotIndex>(&read_snapshot(&config, version_id).await?)
+            .map_err(|source| Error::InvalidJson { path, source })
+    };
+    let from = read(&from).await?;
+    let to = read(&to).await?;
+
+    let mut diff = ModPackDiff::default();
+    for old in &from.files {
+        match to.files.iter().find(|new| new.project() == old.project()) {
+            Some(new) if new.path != old.path => diff.changed.push(ChangedMod {
+                from: old.path.clone(),
+                to: new.path.clone(),
+            }),
+            Some(_) => {}
+            None => diff.removed.push(old.path.clone()),
+        }
+    }
+    diff.added = to
+        .files
+        .iter()
+        .filter(|new| from.files.iter().all(|old| old.project() != new.project()))
+        .map(|new| new.path.clone())
+        .collect();
+    Ok(Json(diff))
+}
+
+/// Zips a modpack index along with the overrides.
+async fn mrpack(config: &Config, json_data: &[u8]) -> Result<impl IntoResponse + use<>, Error> {
     // 2. Create a buffer in memory
     let mut buffer = Vec::new();
 
@@ -222,13 +1173,15 @@ pub async fn generate_mod_pack(config: State<Arc<Config>>) -> Result<impl IntoRe
     {
         let mut zip = zip::ZipWriter::new(Cursor::new(&mut buffer));
 
-        let options =
-            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        let options = config
+            .modpack_compression
+            .unwrap_or_default()
+            .options(config.modpack_compression_level);
 
         // Create the file entry
         zip.start_file("modrinth.index.json", options)
             .map_err(io::Error::other)?;
-        zip.write_all(&json_data)?;
+        zip.write_all(json_data)?;
 
         zip.start_file("overrides/servers.dat", options)
             .map_err(io::Error::other)?;
@@ -286,14 +1239,16 @@ pub async fn server_mods(config: &Config) -> Result<Vec<Mod>, Error> {
                     version: version.to_owned(),
                     mandatory,
                     client_side_only: false,
+                    project_id: config.project_id(name),
                 })
             })
+            .filter(|m| !config.mod_blocked(&m.slug))
             .collect()
             .await,
     )
 }
 
-pub async fn recommended_mods() -> Result<Vec<Mod>, Error> {
+pub async fn recommended_mods(config: &Config) -> Result<Vec<Mod>, Error> {
     static CLIENT_SIDE_MODS: LazyLock<Vec<Mod>> = LazyLock::new(|| {
         [
             "sodium",
@@ -312,26 +1267,72 @@ pub async fn recommended_mods() -> Result<Vec<Mod>, Error> {
             version: LATEST.into(),
             mandatory: false,
             client_side_only: true,
+            project_id: None,
         })
         .to_vec()
     });
 
-    Ok(CLIENT_SIDE_MODS.clone())
+    Ok(CLIENT_SIDE_MODS
+        .iter()
+        .cloned()
+        .map(|m| Mod {
+            project_id: config.project_id(&m.slug),
+            ..m
+        })
+        .collect())
 }
 
-async fn neoforge_version(config: &Config) -> Result<String, Error> {
+pub async fn neoforge_version(config: &Config) -> Result<String, Error> {
     static REGEX: LazyLock<Regex> = LazyLock::new(|| {
         Regex::new(r#"libraries/net/neoforged/neoforge/(.*)/unix_args.txt"#).unwrap()
     });
-    let x = tokio::fs::read_to_string(config.server_dir.join("run.sh")).await?;
-    let captures = REGEX.captures(&x).unwrap();
-    Ok(captures.get(1).unwrap().as_str().to_string())
+    /// The last version read, with the run.sh it was read from and when that was modified.
+    /// Only successful reads are kept so fixing a broken run.sh takes effect right away.
+    static CACHE: LazyLock<std::sync::Mutex<Option<(PathBuf, SystemTime, String)>>> =
+        LazyLock::new(Default::default);
+
+    let path = config.server_dir.join("run.sh");
+    let modified = tokio::fs::metadata(&path).await?.modified()?;
+    if let Some((cached_path, cached_modified, version)) = &*CACHE.lock().unwrap()
+        && *cached_path == path
+        && *cached_modified == modified
+    {
+        return Ok(version.clone());
+    }
+    let x = tokio::fs::read_to_string(&path).await?;
+    let version = REGEX
+        .captures(&x)
+        .map(|captures| captures[1].to_string())
+        .ok_or_else(|| Error::NoLoaderVersion { path: path.clone() })?;
+    *CACHE.lock().unwrap() = Some((path, modified, version.clone()));
+    Ok(version)
+}
+
+pub async fn loader_version(
+    State(AppState { config, .. }): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    Ok((
+        [(CONTENT_TYPE, "text/plain")],
+        neoforge_version(&config).await?,
+    ))
 }
 
-pub async fn get_mods(config: State<Arc<Config>>) -> Result<impl IntoResponse, Error> {
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ModsQuery {
+    filter: Option<String>,
+}
+
+#[utoipa::path(get, path = "/mods", params(ModsQuery), responses((status = 200, body = Mods)))]
+pub async fn get_mods(
+    State(AppState { config, .. }): State<AppState>,
+    headers: HeaderMap,
+    Query(ModsQuery { filter }): Query<ModsQuery>,
+) -> Result<impl IntoResponse, Error> {
     let mut server_mods = server_mods(&config).await?;
-    let recommended_mods = recommended_mods().await?;
+    let recommended_mods = recommended_mods(&config).await?;
     let mut mods = Mods {
+        base_path: config.base_path().to_owned(),
         neoforge_version: neoforge_version(&config).await?,
         required: server_mods.extract_if(.., |m| m.mandatory).collect(),
         recommended: server_mods,
@@ -339,5 +1340,71 @@ pub async fn get_mods(config: State<Arc<Config>>) -> Result<impl IntoResponse, E
     };
     mods.required.sort();
     mods.recommended.sort();
-    Ok(Html(mods.render()?))
+    if let Some(filter) = filter.filter(|f| !f.is_empty()) {
+        let filter = filter.to_lowercase();
+        let matches = |m: &Mod| {
+            m.name.to_lowercase().contains(&filter) || m.slug.to_lowercase().contains(&filter)
+        };
+        mods.required.retain(matches);
+        mods.recommended.retain(matches);
+        mods.client_side.retain(matches);
+    }
+    Ok((
+        config.cache_headers(None),
+        negotiate(&config, &headers, mods)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::ServerDir;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn blocked_mods_are_not_server_mods() {
+        let server = ServerDir::new();
+        server
+            .write("mods/create-1.21.1-6.0.4.jar", "")
+            .write("mods/jei-1.21.1-neoforge-19.21.0.247.jar", "")
+            .write("mods/voicechat-neoforge-1.21.1-2.5.30.jar", "")
+            .write("mods/anticheat-1.0.jar", "");
+        let config = server.config(json!({ "mod_blocklist": ["jei", "simple-voice-chat"] }));
+        let mods = server_mods(&config).await.unwrap();
+        let slugs = mods.iter().map(|m| m.slug.as_str()).collect::<Vec<_>>();
+        assert_eq!(slugs, ["create"]);
+        let unblocked = server_mods(&server.config(json!({}))).await.unwrap();
+        assert_eq!(unblocked.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn slow_modrinth_is_an_upstream_error() {
+        // accepts connections and never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut connections = vec![];
+            while let Ok((connection, _)) = listener.accept().await {
+                connections.push(connection);
+            }
+        });
+        let server = ServerDir::new();
+        let config = server.config(json!({
+            "modrinth_base_url": format!("http://{addr}"),
+            "modrinth_timeout_secs": 1,
+        }));
+        let AppState { config, http, .. } = AppState::new(std::sync::Arc::new(config)).unwrap();
+        let m = Mod {
+            name: "Slow".to_owned(),
+            slug: "slow-modrinth-test".to_owned(),
+            version: LATEST.to_owned(),
+            mandatory: true,
+            client_side_only: false,
+            project_id: None,
+        };
+        let start = Instant::now();
+        let resolved = mod_pack::resolve_mod(&http, m, &config, chrono::Utc::now).await;
+        assert!(matches!(resolved, Err(Error::Upstream(e)) if e.is_timeout()));
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    }
 }
@@ -0,0 +1,112 @@
+use crate::{AppState, Config, Error, logs};
+use axum::{Json, extract::State, response::IntoResponse};
+use chrono::{NaiveDateTime, TimeDelta};
+use futures::StreamExt;
+use serde::Serialize;
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+#[derive(Debug)]
+enum Event {
+    Join(String),
+    Leave(String),
+    ServerStart,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct Playtime {
+    player: String,
+    hours: f64,
+}
+
+/// Sums the duration of every session. Joins without a matching leave, e.g. because the server
+/// crashed, are closed at the next server start or at the end of the logs.
+fn total_playtime(
+    mut events: Vec<(NaiveDateTime, Event)>,
+    end: Option<NaiveDateTime>,
+) -> HashMap<String, TimeDelta> {
+    events.sort_by_key(|(timestamp, _)| *timestamp);
+
+    let mut online = HashMap::<String, NaiveDateTime>::new();
+    let mut totals = HashMap::<String, TimeDelta>::new();
+    let mut close = |player: String, joined: NaiveDateTime, left: NaiveDateTime| {
+        *totals.entry(player).or_default() += left - joined;
+    };
+    for (timestamp, event) in events {
+        match event {
+            Event::Join(player) => {
+                if let Some(joined) = online.insert(player.clone(), timestamp) {
+                    close(player, joined, timestamp);
+                }
+            }
+            Event::Leave(player) => {
+                if let Some(joined) = online.remove(&player) {
+                    close(player, joined, timestamp);
+                }
+            }
+            Event::ServerStart => {
+                for (player, joined) in online.drain() {
+                    close(player, joined, timestamp);
+                }
+            }
+        }
+    }
+    if let Some(end) = end {
+        for (player, joined) in online {
+            close(player, joined, end);
+        }
+    }
+    totals
+}
+
+/// How long each player has been online, according to the logs.
+pub async fn player_playtime(config: &Config) -> Result<HashMap<String, TimeDelta>, Error> {
+    let logs = logs::parse_log_files(config)
+        .await?
+        .collect::<Vec<_>>()
+        .await;
+    let end = logs
+        .iter()
+        .flat_map(|log| {
+            log.lines
+                .iter()
+                .map(|l| l.timestamp)
+                .chain(log.server_starts.iter().copied())
+        })
+        .max();
+    let events = logs
+        .into_iter()
+        .flat_map(|log| {
+            let starts = log
+                .server_starts
+                .into_iter()
+                .map(|timestamp| (timestamp, Event::ServerStart));
+            let sessions = log.lines.into_iter().filter_map(|line| {
+                let event = match line.message.as_str() {
+                    "joined the game" => Event::Join(line.player),
+                    "left the game" => Event::Leave(line.player),
+                    _ => return None,
+                };
+                Some((line.timestamp, event))
+            });
+            starts.chain(sessions)
+        })
+        .collect::<Vec<_>>();
+    Ok(total_playtime(events, end))
+}
+
+#[utoipa::path(get, path = "/playtime", responses((status = 200, body = Vec<Playtime>)))]
+pub async fn playtime(
+    State(AppState { config, .. }): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    let mut playtime = player_playtime(&config)
+        .await?
+        .into_iter()
+        .map(|(player, duration)| Playtime {
+            player,
+            hours: duration.num_seconds() as f64 / 3600.0,
+        })
+        .collect::<Vec<_>>();
+    playtime.sort_by(|a, b| b.hours.total_cmp(&a.hours));
+    Ok(Json(playtime))
+}
@@ -0,0 +1,165 @@
+use crate::{AppState, Error};
+use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
+use serde::{Deserialize, Serialize};
+use std::{io, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use utoipa::ToSchema;
+
+const DEFAULT_PORT: u16 = 25565;
+const PING_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Responses bigger than this aren't a status, the sample and favicon are well below it.
+const MAX_RESPONSE_LEN: usize = 1 << 20;
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+struct Status {
+    online: bool,
+    version: Option<String>,
+    players_online: Option<u64>,
+    players_max: Option<u64>,
+    motd: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StatusResponse {
+    version: Option<StatusVersion>,
+    players: Option<StatusPlayers>,
+    #[serde(default)]
+    description: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct StatusVersion {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct StatusPlayers {
+    max: u64,
+    online: u64,
+}
+
+/// Splits `host:port`, the port being optional as in the multiplayer menu.
+pub fn parse_address(address: &str) -> Option<(&str, u16)> {
+    match address.rsplit_once(':') {
+        Some((host, port)) => Some((host, port.parse().ok()?)),
+        None => Some((address, DEFAULT_PORT)),
+    }
+}
+
+fn write_varint(buf: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+    loop {
+        if value & !0x7f == 0 {
+            buf.push(value as u8);
+            return;
+        }
+        buf.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+}
+
+async fn read_varint(stream: &mut TcpStream) -> io::Result<i32> {
+    let mut value = 0u32;
+    for i in 0..5 {
+        let byte = stream.read_u8().await?;
+        value |= u32::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(value as i32);
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "varint is too long",
+    ))
+}
+
+fn packet(id: i32, data: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    write_varint(&mut body, id);
+    body.extend_from_slice(data);
+    let mut packet = Vec::new();
+    write_varint(&mut packet, body.len() as i32);
+    packet.extend(body);
+    packet
+}
+
+/// Does a server list ping, returning the json the server answers with.
+async fn ping(host: &str, port: u16) -> io::Result<StatusResponse> {
+    let mut stream = TcpStream::connect((host, port)).await?;
+
+    let mut handshake = Vec::new();
+    write_varint(&mut handshake, -1); // no particular protocol version
+    write_varint(&mut handshake, host.len() as i32);
+    handshake.extend_from_slice(host.as_bytes());
+    handshake.extend_from_slice(&port.to_be_bytes());
+    write_varint(&mut handshake, 1); // next state: status
+    stream.write_all(&packet(0x00, &handshake)).await?;
+    stream.write_all(&packet(0x00, &[])).await?;
+
+    let _length = read_varint(&mut stream).await?;
+    let id = read_varint(&mut stream).await?;
+    if id != 0x00 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unexpected packet {id:#x}"),
+        ));
+    }
+    let len = usize::try_from(read_varint(&mut stream).await?)
+        .ok()
+        .filter(|&len| len <= MAX_RESPONSE_LEN)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid status length"))?;
+    let mut json = vec![0; len];
+    stream.read_exact(&mut json).await?;
+    serde_json::from_slice(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// The plain text of a chat component, which the motd is either a string or made of.
+fn plain_text(component: &serde_json::Value, out: &mut String) {
+    match component {
+        serde_json::Value::String(s) => out.push_str(s),
+        serde_json::Value::Array(parts) => parts.iter().for_each(|p| plain_text(p, out)),
+        serde_json::Value::Object(fields) => {
+            if let Some(text) = fields.get("text") {
+                plain_text(text, out);
+            }
+            if let Some(extra) = fields.get("extra") {
+                plain_text(extra, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Whether the server is up and who is on it, as the server reports it in the multiplayer menu.
+#[utoipa::path(get, path = "/status", responses((status = 200, body = Status), (status = 503, body = Status)))]
+pub async fn status(
+    State(AppState { config, .. }): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    let address = config.server_address.as_deref().ok_or(Error::NotFound)?;
+    // the config is validated at startup
+    let (host, port) = parse_address(address).ok_or(Error::NotFound)?;
+    match tokio::time::timeout(PING_TIMEOUT, ping(host, port)).await {
+        Ok(Ok(response)) => {
+            let mut motd = String::new();
+            plain_text(&response.description, &mut motd);
+            Ok((
+                StatusCode::OK,
+                Json(Status {
+                    online: true,
+                    version: response.version.map(|v| v.name),
+                    players_online: response.players.as_ref().map(|p| p.online),
+                    players_max: response.players.map(|p| p.max),
+                    motd: Some(motd),
+                }),
+            ))
+        }
+        result => {
+            tracing::warn!(address, ?result, "server didn't answer the ping");
+            Ok((StatusCode::SERVICE_UNAVAILABLE, Json(Status::default())))
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Fixtures shared by the tests.
+
+use crate::{AppState, Config, SharedState, router};
+use axum::{
+    body::Body,
+    http::{Request, StatusCode, header::ACCEPT},
+    response::Response,
+};
+use flate2::{Compression, write::GzEncoder};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+use tower::ServiceExt as _;
+
+/// A `server_dir` in the temp directory, removed once dropped.
+pub struct ServerDir(PathBuf);
+
+impl ServerDir {
+    pub fn new() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "mc-frontend-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(path.join("logs")).unwrap();
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Writes a file, creating the directories it's in.
+    pub fn write(&self, relative: &str, contents: impl AsRef<[u8]>) -> &Self {
+        let path = self.0.join(relative);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+        self
+    }
+
+    /// Writes a gzipped file, as the server rotates its logs.
+    pub fn write_gz(&self, relative: &str, contents: impl AsRef<[u8]>) -> &Self {
+        self.write(relative, gzip(contents))
+    }
+
+    pub fn whitelist(&self, names: &[&str]) -> &Self {
+        let entries = names
+            .iter()
+            .map(|name| serde_json::json!({ "name": name, "uuid": format!("uuid-{name}") }))
+            .collect::<Vec<_>>();
+        self.write("whitelist.json", serde_json::to_vec(&entries).unwrap())
+    }
+
+    /// A config for this server, with `extra` settings on top of the required ones.
+    pub fn config(&self, extra: serde_json::Value) -> Config {
+        config(self.path(), extra)
+    }
+
+    /// Sends a GET for `uri` to the router served with `config`, returning the status and body.
+    pub async fn get(&self, config: Config, uri: &str) -> (StatusCode, serde_json::Value) {
+        get(state(config), uri).await
+    }
+}
+
+impl Drop for ServerDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A config for the server at `server_dir`, with `extra` settings on top of the required ones.
+pub fn config(server_dir: &Path, extra: serde_json::Value) -> Config {
+    let mut config = serde_json::json!({
+        "backups_dir": server_dir.join("backups"),
+        "server_dir": server_dir,
+    });
+    if let serde_json::Value::Object(extra) = extra {
+        config.as_object_mut().unwrap().extend(extra);
+    }
+    serde_json::from_value::<Config>(config)
+        .unwrap()
+        .validate()
+        .unwrap()
+}
+
+/// The state served with `config`, to send several requests to the same one.
+pub fn state(config: Config) -> SharedState {
+    SharedState(Arc::new(RwLock::new(
+        AppState::new(Arc::new(config)).unwrap(),
+    )))
+}
+
+/// Sends a GET for `uri` asking for json.
+pub async fn request(shared: SharedState, uri: &str) -> Response {
+    router(shared)
+        .oneshot(
+            Request::get(uri)
+                .header(ACCEPT, "application/json")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+}
+
+/// Sends a GET for `uri` asking for json, returning the status and the body, which is `null`
+/// when it isn't json.
+pub async fn get(shared: SharedState, uri: &str) -> (StatusCode, serde_json::Value) {
+    let response = request(shared, uri).await;
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+pub fn gzip(contents: impl AsRef<[u8]>) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(contents.as_ref()).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// A line of `latest.log` as the server writes it, e.g. `log_line("06Jun2025 15:42:05.682",
+/// "alice was slain by Zombie")`.
+pub fn log_line(timestamp: &str, message: &str) -> String {
+    format!(
+        "[{timestamp}] [Server thread/INFO] [net.minecraft.server.MinecraftServer/]: {message}\n"
+    )
+}